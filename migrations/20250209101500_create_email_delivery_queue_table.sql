@@ -0,0 +1,18 @@
+CREATE TABLE email_delivery_queue
+(
+    id              uuid        NOT NULL,
+    PRIMARY KEY (id),
+    recipient_email TEXT        NOT NULL,
+    subject         TEXT        NOT NULL,
+    html_content    TEXT        NOT NULL,
+    text_content    TEXT        NOT NULL,
+    status          TEXT        NOT NULL DEFAULT 'pending',
+    n_attempts      INTEGER     NOT NULL DEFAULT 0,
+    last_error      TEXT        NULL,
+    execute_after   timestamptz NOT NULL DEFAULT now(),
+    enqueued_at     timestamptz NOT NULL DEFAULT now(),
+    completed_at    timestamptz NULL
+);
+CREATE INDEX email_delivery_queue_pending_idx
+    ON email_delivery_queue (execute_after)
+    WHERE status = 'pending';
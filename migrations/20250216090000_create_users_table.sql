@@ -0,0 +1,8 @@
+CREATE TABLE users
+(
+    user_id       uuid NOT NULL,
+    PRIMARY KEY (user_id),
+    username      TEXT NOT NULL UNIQUE,
+    -- Argon2id hash in PHC string format, salt and parameters included
+    password_hash TEXT NOT NULL
+);
@@ -0,0 +1,18 @@
+CREATE TYPE header_pair AS
+(
+    name  TEXT,
+    value BYTEA
+);
+
+CREATE TABLE idempotency
+(
+    -- User id of an authenticated operator, 'anonymous' for public endpoints
+    caller               TEXT        NOT NULL,
+    idempotency_key      TEXT        NOT NULL,
+    -- Saved response, NULL while the first request is still being processed
+    response_status_code SMALLINT    NULL,
+    response_headers     header_pair[] NULL,
+    response_body        BYTEA       NULL,
+    created_at           timestamptz NOT NULL,
+    PRIMARY KEY (caller, idempotency_key)
+);
@@ -0,0 +1,18 @@
+CREATE TABLE newsletter_issues
+(
+    newsletter_issue_id uuid        NOT NULL,
+    PRIMARY KEY (newsletter_issue_id),
+    title               TEXT        NOT NULL,
+    text_content        TEXT        NOT NULL,
+    html_content        TEXT        NOT NULL,
+    published_at        timestamptz NOT NULL
+);
+
+-- One row per recipient of an issue, the delivery status is the one of the queued email
+CREATE TABLE issue_deliveries
+(
+    newsletter_issue_id uuid NOT NULL REFERENCES newsletter_issues (newsletter_issue_id),
+    subscriber_email    TEXT NOT NULL,
+    email_id            uuid NOT NULL REFERENCES email_delivery_queue (id),
+    PRIMARY KEY (newsletter_issue_id, subscriber_email)
+);
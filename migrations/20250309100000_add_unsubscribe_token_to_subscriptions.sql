@@ -0,0 +1,10 @@
+-- Per-subscriber token embedded in the unsubscribe link of every email
+ALTER TABLE subscriptions ADD COLUMN unsubscribe_token TEXT NULL;
+UPDATE subscriptions
+SET unsubscribe_token = replace(gen_random_uuid()::text, '-', '')
+WHERE unsubscribe_token IS NULL;
+ALTER TABLE subscriptions ALTER COLUMN unsubscribe_token SET NOT NULL;
+ALTER TABLE subscriptions ADD CONSTRAINT subscriptions_unsubscribe_token_key UNIQUE (unsubscribe_token);
+
+-- Sent as the List-Unsubscribe header of the queued email
+ALTER TABLE email_delivery_queue ADD COLUMN unsubscribe_url TEXT NULL;
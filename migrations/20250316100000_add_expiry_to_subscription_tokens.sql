@@ -0,0 +1,6 @@
+ALTER TABLE subscription_tokens
+    ADD COLUMN created_at timestamptz NOT NULL DEFAULT now(),
+    -- Existing tokens get a full day from the migration on
+    ADD COLUMN expires_at timestamptz NOT NULL DEFAULT now() + interval '1 day';
+ALTER TABLE subscription_tokens ALTER COLUMN expires_at DROP DEFAULT;
+CREATE INDEX subscription_tokens_expires_at_idx ON subscription_tokens (expires_at);
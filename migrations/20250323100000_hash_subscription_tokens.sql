@@ -0,0 +1,4 @@
+-- Only a SHA-256 digest of each confirmation token is kept, hex-encoded
+ALTER TABLE subscription_tokens RENAME COLUMN subscription_token TO subscription_token_hash;
+UPDATE subscription_tokens
+SET subscription_token_hash = encode(sha256(convert_to(subscription_token_hash, 'UTF8')), 'hex');
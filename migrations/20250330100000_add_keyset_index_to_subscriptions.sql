@@ -0,0 +1,2 @@
+-- Keyset pagination of subscribers, see `pagination.rs`
+CREATE INDEX subscriptions_subscribed_at_id_idx ON subscriptions (subscribed_at, id);
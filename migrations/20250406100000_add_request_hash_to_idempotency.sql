@@ -0,0 +1,3 @@
+-- SHA-256 of the method, path and body of the request that claimed the key,
+-- NULL for keys saved before it was recorded
+ALTER TABLE idempotency ADD COLUMN request_hash TEXT NULL;
@@ -0,0 +1,6 @@
+-- Start of the confirmation window of a pending subscriber, restarted when they subscribe again.
+-- subscribed_at never changes, as it orders the paginated listing of subscribers.
+ALTER TABLE subscriptions ADD COLUMN confirmation_requested_at timestamptz NULL;
+UPDATE subscriptions SET confirmation_requested_at = subscribed_at;
+ALTER TABLE subscriptions ALTER COLUMN confirmation_requested_at SET DEFAULT now();
+ALTER TABLE subscriptions ALTER COLUMN confirmation_requested_at SET NOT NULL;
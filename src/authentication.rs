@@ -0,0 +1,359 @@
+use crate::configuration::OperatorSettings;
+use crate::routes::error_chain_fmt;
+use crate::session_state::TypedSession;
+use crate::utils::{e500, see_other};
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::http::header::{self, HeaderMap, HeaderValue};
+use actix_web::http::StatusCode;
+use actix_web::middleware::Next;
+use actix_web::{web, FromRequest, HttpMessage, HttpRequest, HttpResponse, ResponseError};
+use anyhow::Context;
+use argon2::password_hash::SaltString;
+use argon2::{Algorithm, Argon2, Params, PasswordHash, PasswordHasher, PasswordVerifier, Version};
+use base64::Engine;
+use sqlx::PgPool;
+use std::future::Future;
+use std::pin::Pin;
+use uuid::Uuid;
+
+/// Username and password as sent by the client
+pub struct Credentials {
+    pub username: String,
+    pub password: String,
+}
+
+#[derive(thiserror::Error)]
+pub enum AuthError {
+    #[error("Invalid credentials")]
+    InvalidCredentials(#[source] anyhow::Error),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for AuthError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for AuthError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AuthError::InvalidCredentials(_) => StatusCode::UNAUTHORIZED,
+            AuthError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            AuthError::InvalidCredentials(_) => {
+                tracing::warn!(error.cause_chain = ?self, "Authentication failed");
+                HttpResponse::Unauthorized()
+                    .insert_header((
+                        header::WWW_AUTHENTICATE,
+                        HeaderValue::from_static(r#"Basic realm="publish""#),
+                    ))
+                    .finish()
+            }
+            AuthError::UnexpectedError(_) => {
+                tracing::error!(error.cause_chain = ?self, "Failed to authenticate a request");
+                HttpResponse::new(self.status_code())
+            }
+        }
+    }
+}
+
+/// Operator authenticated through HTTP Basic credentials
+#[derive(Debug, Clone)]
+pub struct AuthenticatedUser {
+    pub user_id: Uuid,
+    pub username: String,
+}
+
+impl FromRequest for AuthenticatedUser {
+    type Error = AuthError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(request: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        // Already authenticated by `reject_anonymous_users`
+        if let Some(user) = request.extensions().get::<AuthenticatedUser>() {
+            let user = user.clone();
+            return Box::pin(async move { Ok(user) });
+        }
+        let credentials = basic_authentication(request.headers());
+        let pool = request.app_data::<web::Data<PgPool>>().cloned();
+        Box::pin(async move {
+            let credentials = credentials.map_err(AuthError::InvalidCredentials)?;
+            let pool = pool.context("No database pool registered in the application")?;
+            let username = credentials.username.clone();
+            let user_id = validate_credentials(credentials, &pool).await?;
+            Ok(AuthenticatedUser { user_id, username })
+        })
+    }
+}
+
+/// Middleware rejecting requests without valid Basic credentials, for use with
+/// [`actix_web::middleware::from_fn`] on the scopes to protect
+pub async fn reject_anonymous_users(
+    mut request: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let user = request.extract::<AuthenticatedUser>().await?;
+    request.extensions_mut().insert(user);
+    next.call(request).await
+}
+
+/// Id of the operator logged in through the session, set by [`reject_logged_out_users`]
+#[derive(Copy, Clone, Debug)]
+pub struct UserId(Uuid);
+
+impl std::fmt::Display for UserId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl std::ops::Deref for UserId {
+    type Target = Uuid;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/// Middleware redirecting requests without a logged-in session to the login form, for use with
+/// [`actix_web::middleware::from_fn`] on the scopes to protect
+pub async fn reject_logged_out_users(
+    mut request: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
+    let session = request.extract::<TypedSession>().await?;
+    match session.get_user_id().map_err(e500)? {
+        Some(user_id) => {
+            request.extensions_mut().insert(UserId(user_id));
+            next.call(request)
+                .await
+                .map(ServiceResponse::map_into_left_body)
+        }
+        None => {
+            let response = see_other("/login");
+            Ok(request.into_response(response).map_into_right_body())
+        }
+    }
+}
+
+/// Extract the credentials from an `Authorization: Basic` header
+pub fn basic_authentication(headers: &HeaderMap) -> Result<Credentials, anyhow::Error> {
+    let header_value = headers
+        .get(header::AUTHORIZATION)
+        .context("The 'Authorization' header was missing")?
+        .to_str()
+        .context("The 'Authorization' header was not a valid UTF8 string")?;
+    let base64encoded_segment = header_value
+        .strip_prefix("Basic ")
+        .context("The authorization scheme was not 'Basic'")?;
+    let decoded_bytes = base64::engine::general_purpose::STANDARD
+        .decode(base64encoded_segment)
+        .context("Failed to base64-decode 'Basic' credentials")?;
+    let decoded_credentials = String::from_utf8(decoded_bytes)
+        .context("The decoded credential string is not valid UTF8")?;
+    let (username, password) = decoded_credentials
+        .split_once(':')
+        .context("A username and a password must be provided in 'Basic' auth")?;
+    Ok(Credentials {
+        username: username.to_string(),
+        password: password.to_string(),
+    })
+}
+
+/// Check the credentials against the stored password hash and return the matching user id
+#[tracing::instrument(name = "Validate credentials", skip(credentials, pool))]
+pub async fn validate_credentials(
+    credentials: Credentials,
+    pool: &PgPool,
+) -> Result<Uuid, AuthError> {
+    let mut user_id = None;
+    // Verify against a dummy hash for unknown usernames, so that the response time
+    // does not tell whether the user exists
+    let mut expected_password_hash = DUMMY_PASSWORD_HASH.to_string();
+    if let Some((stored_user_id, stored_password_hash)) =
+        get_stored_credentials(&credentials.username, pool).await?
+    {
+        user_id = Some(stored_user_id);
+        expected_password_hash = stored_password_hash;
+    }
+    spawn_blocking_with_tracing(move || {
+        verify_password_hash(&expected_password_hash, &credentials.password)
+    })
+    .await
+    .context("Failed to spawn blocking task")??;
+    user_id
+        .ok_or_else(|| anyhow::anyhow!("Unknown username"))
+        .map_err(AuthError::InvalidCredentials)
+}
+
+/// Argon2id hash of a random password, with the same parameters as [`compute_password_hash`]
+const DUMMY_PASSWORD_HASH: &str = "$argon2id$v=19$m=15000,t=2,p=1$\
+    gZiV/M1gPc22ElAH/Jh1Hw$CWOrkoo7oJBQ/iyh7uJ0LO2aLEfrHwTWllSAxT0zRno";
+
+#[tracing::instrument(name = "Get stored credentials", skip(username, pool))]
+async fn get_stored_credentials(
+    username: &str,
+    pool: &PgPool,
+) -> Result<Option<(Uuid, String)>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+SELECT user_id, password_hash
+FROM users
+WHERE username = $1
+"#,
+        username,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to perform a query to retrieve stored credentials")?
+    .map(|row| (row.user_id, row.password_hash));
+    Ok(row)
+}
+
+#[tracing::instrument(name = "Verify password hash", skip(expected_password_hash, password))]
+fn verify_password_hash(expected_password_hash: &str, password: &str) -> Result<(), AuthError> {
+    let expected_password_hash = PasswordHash::new(expected_password_hash)
+        .context("Failed to parse hash in PHC string format")?;
+    Argon2::default()
+        .verify_password(password.as_bytes(), &expected_password_hash)
+        .context("Invalid password")
+        .map_err(AuthError::InvalidCredentials)
+}
+
+/// Store a new Argon2id hash of the password of the given operator
+#[tracing::instrument(name = "Change password", skip(password, pool))]
+pub async fn change_password(
+    user_id: Uuid,
+    password: String,
+    pool: &PgPool,
+) -> Result<(), anyhow::Error> {
+    let password_hash = spawn_blocking_with_tracing(move || compute_password_hash(&password))
+        .await?
+        .context("Failed to hash password")?;
+    sqlx::query!(
+        r#"
+UPDATE users
+SET password_hash = $1
+WHERE user_id = $2
+"#,
+        password_hash,
+        user_id
+    )
+    .execute(pool)
+    .await
+    .context("Failed to change user's password in the database")?;
+    Ok(())
+}
+
+/// Create the operator account from the configuration if there is no user yet
+#[tracing::instrument(name = "Seed operator account", skip(settings, pool))]
+pub async fn seed_operator(
+    settings: &OperatorSettings,
+    pool: &PgPool,
+) -> Result<(), anyhow::Error> {
+    let has_users = sqlx::query!(r#"SELECT EXISTS (SELECT 1 FROM users) AS "exists!""#)
+        .fetch_one(pool)
+        .await
+        .context("Failed to check for existing users")?
+        .exists;
+    if has_users {
+        return Ok(());
+    }
+    let password = settings
+        .password
+        .clone()
+        .context("There is no user yet: an operator password must be configured")?;
+    let password_hash = spawn_blocking_with_tracing(move || compute_password_hash(&password))
+        .await?
+        .context("Failed to hash password")?;
+    // Another instance may have seeded the account in the meantime
+    sqlx::query!(
+        r#"
+INSERT INTO users (user_id, username, password_hash)
+SELECT $1, $2, $3
+WHERE NOT EXISTS (SELECT 1 FROM users)
+"#,
+        Uuid::new_v4(),
+        settings.username,
+        password_hash
+    )
+    .execute(pool)
+    .await
+    .context("Failed to store the operator account")?;
+    Ok(())
+}
+
+/// Hash a password with Argon2id, using a fresh random salt
+pub fn compute_password_hash(password: &str) -> Result<String, anyhow::Error> {
+    let salt = SaltString::generate(&mut rand::thread_rng());
+    let password_hash = Argon2::new(
+        Algorithm::Argon2id,
+        Version::V0x13,
+        Params::new(15000, 2, 1, None).unwrap(),
+    )
+    .hash_password(password.as_bytes(), &salt)?
+    .to_string();
+    Ok(password_hash)
+}
+
+/// Run CPU-bound work on the blocking thread pool, inside the current tracing span
+pub fn spawn_blocking_with_tracing<F, R>(f: F) -> tokio::task::JoinHandle<R>
+where
+    F: FnOnce() -> R + Send + 'static,
+    R: Send + 'static,
+{
+    let current_span = tracing::Span::current();
+    tokio::task::spawn_blocking(move || current_span.in_scope(f))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(authorization: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_str(authorization).unwrap(),
+        );
+        headers
+    }
+    #[test]
+    fn basic_credentials_are_decoded() {
+        // Arrange
+        let encoded = base64::engine::general_purpose::STANDARD.encode("ursula:pass:word");
+        // Act
+        let credentials = basic_authentication(&headers(&format!("Basic {encoded}"))).unwrap();
+        // Assert
+        assert_eq!(credentials.username, "ursula");
+        assert_eq!(credentials.password, "pass:word");
+    }
+    #[test]
+    fn other_authorization_schemes_are_rejected() {
+        assert!(basic_authentication(&headers("Bearer token")).is_err());
+        assert!(basic_authentication(&HeaderMap::new()).is_err());
+    }
+    #[test]
+    fn a_computed_hash_verifies_only_the_same_password() {
+        // Arrange
+        let hash = compute_password_hash("correct horse").unwrap();
+        // Act & Assert
+        assert!(hash.starts_with("$argon2id$"));
+        assert!(verify_password_hash(&hash, "correct horse").is_ok());
+        assert!(matches!(
+            verify_password_hash(&hash, "battery staple"),
+            Err(AuthError::InvalidCredentials(_))
+        ));
+    }
+    #[test]
+    fn the_dummy_hash_is_a_valid_argon2id_hash() {
+        let hash = PasswordHash::new(DUMMY_PASSWORD_HASH).unwrap();
+        assert_eq!(hash.algorithm, argon2::ARGON2ID_IDENT);
+    }
+}
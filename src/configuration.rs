@@ -8,6 +8,16 @@ pub struct Settings {
     pub database: DatabaseSettings,
     pub application: ApplicationSettings,
     pub email: EmailSettings,
+    pub subscriptions: SubscriptionSettings,
+    pub operator: OperatorSettings,
+}
+
+/// Operator account created on startup when there is no user yet
+#[derive(Deserialize, Debug)]
+pub struct OperatorSettings {
+    pub username: String,
+    /// Required to create the account, to be provided by the environment rather than committed
+    pub password: Option<String>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -15,6 +25,11 @@ pub struct ApplicationSettings {
     #[serde(deserialize_with = "deserialize_number_from_string")]
     pub port: u16,
     pub host: String,
+    pub base_url: String,
+    /// Key signing session and flash message cookies, at least 64 bytes long.
+    ///
+    /// Required, and never committed: it comes from the environment or `secrets.yaml`.
+    pub hmac_secret: Option<String>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -48,32 +63,158 @@ impl DatabaseSettings {
     }
 }
 
+/// Load `configuration/base.yaml`, then the file of `ZERO_ENVIRONMENT` (`local` by default), then
+/// the git-ignored `configuration/secrets.yaml` if any, then the `ZERO_`-prefixed environment
+/// variables, whose nested keys are separated by a double underscore, e.g.
+/// `ZERO_APPLICATION__BASE_URL` for `application.base_url`
 pub fn get() -> Result<Settings, config::ConfigError> {
     let base_path = std::env::current_dir().expect("Failed to determine the current directory");
     let configuration_directory = base_path.join("configuration");
     let environment = std::env::var("ZERO_ENVIRONMENT").unwrap_or("local".to_string());
     let base = configuration_directory.join("base");
     let file = configuration_directory.join(environment);
+    let secrets = configuration_directory.join("secrets");
     let config = config::Config::builder()
         .add_source(config::File::from(base).required(true))
         .add_source(config::File::from(file).required(false))
+        .add_source(config::File::from(secrets).required(false))
         .add_source(
             config::Environment::with_prefix("zero")
                 .try_parsing(true)
-                .separator("_"),
+                .prefix_separator("_")
+                .separator("__"),
         )
         .build()?;
     config.try_deserialize()
 }
 #[derive(Deserialize, Debug)]
 pub struct EmailSettings {
+    #[serde(default)]
+    pub provider: EmailProvider,
     pub base_url: String,
     pub sender: String,
+    pub sender_name: String,
+    pub list_id: String,
     pub apikey: String,
     pub timeout: u64,
+    pub retry: RetrySettings,
+    pub worker: DeliveryWorkerSettings,
+    pub smtp: Option<SmtpSettings>,
 }
 impl EmailSettings {
     pub fn timeout(&self) -> std::time::Duration {
         std::time::Duration::from_millis(self.timeout)
     }
 }
+
+/// Retry policy for failed email deliveries, delays in milliseconds
+#[derive(Deserialize, Debug)]
+pub struct RetrySettings {
+    pub max_attempts: u32,
+    pub base_delay: u64,
+    pub max_delay: u64,
+    pub jitter: bool,
+    pub retryable_status_codes: Vec<u16>,
+}
+
+/// Background delivery of the outbound email queue
+#[derive(Deserialize, Debug)]
+pub struct DeliveryWorkerSettings {
+    pub enabled: bool,
+    /// Milliseconds to wait before polling an empty queue again
+    pub poll_interval: u64,
+    /// Rescheduling of failed deliveries, on top of the retries done by the email client
+    pub retry: RetrySettings,
+}
+impl DeliveryWorkerSettings {
+    pub fn poll_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.poll_interval)
+    }
+}
+
+/// Lifecycle of pending subscriptions
+#[derive(Deserialize, Debug)]
+pub struct SubscriptionSettings {
+    /// Milliseconds during which a confirmation link stays valid
+    pub token_ttl: u64,
+    pub cleanup: CleanupSettings,
+    pub confirmation_tokens: ConfirmationTokenSettings,
+}
+impl SubscriptionSettings {
+    pub fn token_ttl(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.token_ttl)
+    }
+}
+
+/// How confirmation links identify the subscriber
+#[derive(Deserialize, Debug, Clone)]
+pub struct ConfirmationTokenSettings {
+    #[serde(default)]
+    pub mode: ConfirmationTokenMode,
+    /// Key signing the tokens, required in `signed` mode and never committed
+    pub signing_key: Option<String>,
+}
+impl ConfirmationTokenSettings {
+    /// Key of the `signed` mode, whose presence is checked on startup
+    pub fn signing_key(&self) -> &[u8] {
+        self.signing_key.as_deref().unwrap_or_default().as_bytes()
+    }
+}
+
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ConfirmationTokenMode {
+    /// Random tokens, whose digest is stored in `subscription_tokens`
+    #[default]
+    Table,
+    /// HMAC-signed tokens verified without any database lookup.
+    ///
+    /// Pending subscribers are cleaned up once they subscribed longer than the token TTL ago.
+    Signed,
+}
+
+/// Periodic removal of expired tokens and of the subscribers who never confirmed
+#[derive(Deserialize, Debug)]
+pub struct CleanupSettings {
+    pub enabled: bool,
+    /// Milliseconds between two cleanups
+    pub interval: u64,
+}
+impl CleanupSettings {
+    pub fn interval(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.interval)
+    }
+}
+
+/// Backend used to deliver emails
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EmailProvider {
+    #[default]
+    Unisender,
+    Smtp,
+    InMemory,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SmtpSettings {
+    pub host: String,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub port: u16,
+    pub username: String,
+    pub password: String,
+    #[serde(default)]
+    pub tls: SmtpTls,
+}
+
+/// How the connection to the SMTP relay is secured
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SmtpTls {
+    /// Plain connection, only suitable for local relays
+    None,
+    #[default]
+    Starttls,
+    /// Implicit TLS, usually on port 465
+    Tls,
+}
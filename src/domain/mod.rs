@@ -1,6 +1,12 @@
 mod new_subscriber;
+mod signed_token;
 mod subscriber_email;
 mod subscriber_name;
+mod subscription_status;
+mod subscription_token;
 pub use new_subscriber::NewSubscriber;
+pub use signed_token::{SignedToken, SignedTokenError};
 pub use subscriber_email::SubscriberEmail;
 pub use subscriber_name::SubscriberName;
+pub use subscription_status::SubscriptionStatus;
+pub use subscription_token::SubscriptionToken;
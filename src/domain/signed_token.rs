@@ -0,0 +1,226 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use uuid::Uuid;
+
+/// Token carrying its own claims, authenticated with HMAC-SHA256 instead of being stored.
+///
+/// Its string form is `<subscriber id>.<issued at>.<purpose>.<signature>`, where the signature
+/// covers everything before the last dot.
+#[derive(Debug, PartialEq, Eq)]
+pub struct SignedToken {
+    pub subscriber_id: Uuid,
+    pub issued_at: DateTime<Utc>,
+    pub purpose: String,
+}
+
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum SignedTokenError {
+    #[error("The token is malformed")]
+    Malformed,
+    #[error("The token signature does not match its content")]
+    InvalidSignature,
+    #[error("The token was issued for another purpose")]
+    WrongPurpose,
+    #[error("The token has expired")]
+    Expired,
+}
+
+impl SignedToken {
+    /// Purpose of the tokens sent in confirmation emails
+    pub const SUBSCRIPTION_CONFIRMATION: &'static str = "subscription_confirmation";
+
+    pub fn new(subscriber_id: Uuid, purpose: &str) -> Self {
+        Self {
+            subscriber_id,
+            issued_at: Utc::now(),
+            purpose: purpose.to_string(),
+        }
+    }
+
+    pub fn sign(&self, key: &[u8]) -> String {
+        let payload = format!(
+            "{}.{}.{}",
+            self.subscriber_id,
+            self.issued_at.timestamp(),
+            self.purpose
+        );
+        let signature = URL_SAFE_NO_PAD.encode(mac(key, &payload).finalize().into_bytes());
+        format!("{payload}.{signature}")
+    }
+
+    /// Check the signature, then that the token was issued for `purpose` less than `ttl` ago
+    pub fn verify(
+        token: &str,
+        key: &[u8],
+        purpose: &str,
+        ttl: chrono::Duration,
+    ) -> Result<SignedToken, SignedTokenError> {
+        Self::verify_at(token, key, purpose, ttl, Utc::now())
+    }
+
+    fn verify_at(
+        token: &str,
+        key: &[u8],
+        purpose: &str,
+        ttl: chrono::Duration,
+        now: DateTime<Utc>,
+    ) -> Result<SignedToken, SignedTokenError> {
+        let (payload, signature) = token.rsplit_once('.').ok_or(SignedTokenError::Malformed)?;
+        let signature = URL_SAFE_NO_PAD
+            .decode(signature)
+            .map_err(|_| SignedTokenError::Malformed)?;
+        // Constant-time comparison
+        mac(key, payload)
+            .verify_slice(&signature)
+            .map_err(|_| SignedTokenError::InvalidSignature)?;
+        let token = Self::parse_payload(payload)?;
+        if token.purpose != purpose {
+            return Err(SignedTokenError::WrongPurpose);
+        }
+        if token.issued_at + ttl <= now {
+            return Err(SignedTokenError::Expired);
+        }
+        Ok(token)
+    }
+
+    fn parse_payload(payload: &str) -> Result<SignedToken, SignedTokenError> {
+        let mut parts = payload.splitn(3, '.');
+        let (Some(subscriber_id), Some(issued_at), Some(purpose)) =
+            (parts.next(), parts.next(), parts.next())
+        else {
+            return Err(SignedTokenError::Malformed);
+        };
+        let subscriber_id = subscriber_id
+            .parse()
+            .map_err(|_| SignedTokenError::Malformed)?;
+        let issued_at = issued_at
+            .parse()
+            .ok()
+            .and_then(|seconds| DateTime::from_timestamp(seconds, 0))
+            .ok_or(SignedTokenError::Malformed)?;
+        Ok(SignedToken {
+            subscriber_id,
+            issued_at,
+            purpose: purpose.to_string(),
+        })
+    }
+}
+
+fn mac(key: &[u8], payload: &str) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(payload.as_bytes());
+    mac
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &[u8] = b"a-signing-key-only-used-in-tests";
+    const PURPOSE: &str = SignedToken::SUBSCRIPTION_CONFIRMATION;
+
+    fn ttl() -> chrono::Duration {
+        chrono::Duration::hours(24)
+    }
+    fn verify(token: &str) -> Result<SignedToken, SignedTokenError> {
+        SignedToken::verify(token, KEY, PURPOSE, ttl())
+    }
+
+    #[test]
+    fn a_signed_token_verifies_to_its_claims() {
+        // Arrange
+        let subscriber_id = Uuid::new_v4();
+        let token = SignedToken::new(subscriber_id, PURPOSE);
+        // Act
+        let verified = verify(&token.sign(KEY)).unwrap();
+        // Assert
+        assert_eq!(verified.subscriber_id, subscriber_id);
+        assert_eq!(verified.purpose, PURPOSE);
+        assert_eq!(verified.issued_at.timestamp(), token.issued_at.timestamp());
+    }
+    #[test]
+    fn signed_tokens_can_be_used_in_a_query_string() {
+        let token = SignedToken::new(Uuid::new_v4(), PURPOSE).sign(KEY);
+        assert!(token
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c)));
+    }
+    #[test]
+    fn a_token_with_a_tampered_subscriber_id_is_rejected() {
+        // Arrange
+        let token = SignedToken::new(Uuid::new_v4(), PURPOSE).sign(KEY);
+        let (_, rest) = token.split_once('.').unwrap();
+        // Act
+        let tampered = format!("{}.{rest}", Uuid::new_v4());
+        // Assert
+        assert_eq!(verify(&tampered), Err(SignedTokenError::InvalidSignature));
+    }
+    #[test]
+    fn a_token_with_a_tampered_issue_date_is_rejected() {
+        // Arrange
+        let mut token = SignedToken::new(Uuid::new_v4(), PURPOSE);
+        let signed = token.sign(KEY);
+        let (_, signature) = signed.rsplit_once('.').unwrap();
+        // Act
+        token.issued_at += chrono::Duration::days(30);
+        let resigned = token.sign(KEY);
+        let (payload, _) = resigned.rsplit_once('.').unwrap();
+        let tampered = format!("{payload}.{signature}");
+        // Assert
+        assert_eq!(verify(&tampered), Err(SignedTokenError::InvalidSignature));
+    }
+    #[test]
+    fn a_token_with_a_tampered_signature_is_rejected() {
+        // Arrange
+        let token = SignedToken::new(Uuid::new_v4(), PURPOSE).sign(KEY);
+        let (payload, _) = token.rsplit_once('.').unwrap();
+        // Act
+        let tampered = format!("{payload}.{}", URL_SAFE_NO_PAD.encode([0u8; 32]));
+        // Assert
+        assert_eq!(verify(&tampered), Err(SignedTokenError::InvalidSignature));
+    }
+    #[test]
+    fn a_token_signed_with_another_key_is_rejected() {
+        let token = SignedToken::new(Uuid::new_v4(), PURPOSE).sign(b"another-key");
+        assert_eq!(verify(&token), Err(SignedTokenError::InvalidSignature));
+    }
+    #[test]
+    fn a_token_issued_for_another_purpose_is_rejected() {
+        let token = SignedToken::new(Uuid::new_v4(), "password_reset").sign(KEY);
+        assert_eq!(verify(&token), Err(SignedTokenError::WrongPurpose));
+    }
+    #[test]
+    fn a_token_older_than_the_ttl_is_rejected() {
+        // Arrange
+        let mut token = SignedToken::new(Uuid::new_v4(), PURPOSE);
+        token.issued_at -= ttl() + chrono::Duration::seconds(1);
+        // Act
+        let outcome = verify(&token.sign(KEY));
+        // Assert
+        assert_eq!(outcome, Err(SignedTokenError::Expired));
+    }
+    #[test]
+    fn a_token_is_valid_until_the_end_of_the_ttl() {
+        // Arrange
+        let token = SignedToken::new(Uuid::new_v4(), PURPOSE);
+        let signed = token.sign(KEY);
+        let issued_at = DateTime::from_timestamp(token.issued_at.timestamp(), 0).unwrap();
+        // Act & Assert
+        let just_before = issued_at + ttl() - chrono::Duration::seconds(1);
+        assert!(SignedToken::verify_at(&signed, KEY, PURPOSE, ttl(), just_before).is_ok());
+        let at_expiry = issued_at + ttl();
+        assert_eq!(
+            SignedToken::verify_at(&signed, KEY, PURPOSE, ttl(), at_expiry),
+            Err(SignedTokenError::Expired)
+        );
+    }
+    #[test]
+    fn malformed_tokens_are_rejected() {
+        for token in ["", "no-dots", "a.b", "a.b.c.d", "a.b.c.!!!"] {
+            assert!(verify(token).is_err(), "{token} was accepted");
+        }
+    }
+}
@@ -15,7 +15,7 @@ impl SubscriberEmail {
         };
         match res.validate() {
             Ok(_) => Ok(Self(s.to_string())),
-            Err(_) => Err(String::from("invalid email")),
+            Err(_) => Err(format!("'{s}' is not a valid email address")),
         }
     }
 }
@@ -4,12 +4,16 @@ pub struct SubscriberName(String);
 
 impl SubscriberName {
     pub fn parse(s: &str) -> Result<SubscriberName, String> {
-        let is_empty_or_whitespace = s.trim().is_empty();
-        let is_too_long = s.graphemes(true).count() > 256;
         let forbidden_characters = ['/', '(', ')', '"', '<', '>', '\\', '{', '}'];
-        let contains_forbidden_characters = s.chars().any(|g| forbidden_characters.contains(&g));
-        if is_empty_or_whitespace || is_too_long || contains_forbidden_characters {
-            Err(format!("Subscriber name {s} is invalid"))
+        if s.trim().is_empty() {
+            Err("Subscriber name is empty".to_string())
+        } else if s.graphemes(true).count() > 256 {
+            Err("Subscriber name is longer than 256 characters".to_string())
+        } else if s.chars().any(|g| forbidden_characters.contains(&g)) {
+            Err(format!(
+                "Subscriber name must not contain any of {}",
+                String::from_iter(forbidden_characters)
+            ))
         } else {
             Ok(Self(s.to_string()))
         }
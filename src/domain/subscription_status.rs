@@ -0,0 +1,52 @@
+/// Lifecycle state of a subscription, stored as text in `subscriptions.status`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubscriptionStatus {
+    PendingConfirmation,
+    Confirmed,
+    Unsubscribed,
+}
+
+impl SubscriptionStatus {
+    const ALL: [SubscriptionStatus; 3] = [
+        SubscriptionStatus::PendingConfirmation,
+        SubscriptionStatus::Confirmed,
+        SubscriptionStatus::Unsubscribed,
+    ];
+
+    pub fn parse(s: &str) -> Result<SubscriptionStatus, String> {
+        Self::ALL
+            .into_iter()
+            .find(|status| status.as_ref() == s)
+            .ok_or_else(|| {
+                let known: Vec<_> = Self::ALL.iter().map(AsRef::as_ref).collect();
+                format!("'{s}' is not one of {}", known.join(", "))
+            })
+    }
+}
+
+impl AsRef<str> for SubscriptionStatus {
+    fn as_ref(&self) -> &str {
+        match self {
+            SubscriptionStatus::PendingConfirmation => "pending_confirmation",
+            SubscriptionStatus::Confirmed => "confirmed",
+            SubscriptionStatus::Unsubscribed => "unsubscribed",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn every_status_parses_back_from_its_stored_form() {
+        for status in SubscriptionStatus::ALL {
+            assert_eq!(SubscriptionStatus::parse(status.as_ref()), Ok(status));
+        }
+    }
+    #[test]
+    fn unknown_statuses_are_rejected() {
+        assert!(SubscriptionStatus::parse("").is_err());
+        assert!(SubscriptionStatus::parse("Confirmed").is_err());
+        assert!(SubscriptionStatus::parse("deleted").is_err());
+    }
+}
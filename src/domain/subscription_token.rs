@@ -0,0 +1,98 @@
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use sha2::{Digest, Sha256};
+
+const TOKEN_LENGTH: usize = 25;
+
+/// Random token sent to a subscriber. Only its [`hash`](SubscriptionToken::hash) is stored.
+#[derive(Debug)]
+pub struct SubscriptionToken(String);
+
+impl SubscriptionToken {
+    /// Generate a random 25-characters-long case-sensitive token
+    pub fn generate() -> SubscriptionToken {
+        let mut rng = thread_rng();
+        let token = std::iter::repeat_with(|| rng.sample(Alphanumeric))
+            .map(char::from)
+            .take(TOKEN_LENGTH)
+            .collect();
+        Self(token)
+    }
+
+    pub fn parse(s: &str) -> Result<SubscriptionToken, String> {
+        if s.len() != TOKEN_LENGTH {
+            Err(format!(
+                "Subscription token is not {TOKEN_LENGTH} characters long"
+            ))
+        } else if !s.chars().all(|c| c.is_ascii_alphanumeric()) {
+            Err("Subscription token must only contain ASCII letters and digits".to_string())
+        } else {
+            Ok(Self(s.to_string()))
+        }
+    }
+
+    /// Hex-encoded SHA-256 digest, the form under which the token is stored and looked up.
+    ///
+    /// The token is random enough that neither a salt nor a slow hash is needed.
+    pub fn hash(&self) -> String {
+        format!("{:x}", Sha256::digest(self.0.as_bytes()))
+    }
+}
+
+impl AsRef<str> for SubscriptionToken {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn generated_tokens_are_valid() {
+        let token = SubscriptionToken::generate();
+        assert!(SubscriptionToken::parse(token.as_ref()).is_ok());
+    }
+    #[test]
+    fn generated_tokens_are_unique() {
+        let first = SubscriptionToken::generate();
+        let second = SubscriptionToken::generate();
+        assert_ne!(first.as_ref(), second.as_ref());
+    }
+    #[test]
+    fn tokens_of_the_wrong_length_are_rejected() {
+        assert!(SubscriptionToken::parse("").is_err());
+        assert!(SubscriptionToken::parse(&"a".repeat(24)).is_err());
+        assert!(SubscriptionToken::parse(&"a".repeat(26)).is_err());
+    }
+    #[test]
+    fn tokens_with_non_alphanumeric_characters_are_rejected() {
+        assert!(SubscriptionToken::parse(&format!("{}-", "a".repeat(24))).is_err());
+        assert!(SubscriptionToken::parse(&format!("{}é", "a".repeat(23))).is_err());
+    }
+    #[test]
+    fn the_hash_is_the_hex_encoded_sha256_of_the_token() {
+        // Arrange
+        let token = SubscriptionToken::parse("abcdefghijklmnopqrstuvwxy").unwrap();
+        // Act
+        let hash = token.hash();
+        // Assert
+        assert_eq!(
+            hash,
+            "69b980549d5045969285133df773ae91ddd5d0e5c73dc8ee959b2eb223bc5fbb"
+        );
+    }
+    #[test]
+    fn the_hash_does_not_contain_the_token() {
+        let token = SubscriptionToken::generate();
+        let hash = token.hash();
+        assert_eq!(hash.len(), 64);
+        assert!(!hash.contains(token.as_ref()));
+    }
+    #[test]
+    fn the_same_token_always_hashes_to_the_same_digest() {
+        let token = SubscriptionToken::generate();
+        let parsed = SubscriptionToken::parse(token.as_ref()).unwrap();
+        assert_eq!(token.hash(), parsed.hash());
+    }
+}
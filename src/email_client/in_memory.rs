@@ -0,0 +1,90 @@
+use crate::domain::SubscriberEmail;
+use crate::email_client::{EmailClientError, EmailSender};
+use std::sync::Mutex;
+
+/// Email sent through [`InMemoryEmailClient`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct SentEmail {
+    pub recipient: String,
+    pub subject: String,
+    pub html_content: String,
+    pub text_content: String,
+    pub headers: Vec<(String, String)>,
+}
+
+/// Keeps every email in memory instead of delivering it
+#[derive(Debug, Default)]
+pub struct InMemoryEmailClient {
+    sent: Mutex<Vec<SentEmail>>,
+}
+
+impl InMemoryEmailClient {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Emails recorded so far, in the order they were sent
+    pub fn sent_emails(&self) -> Vec<SentEmail> {
+        self.sent.lock().expect("Email log lock poisoned").clone()
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailSender for InMemoryEmailClient {
+    async fn send_email_with_headers(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        headers: &[(&str, &str)],
+    ) -> Result<(), EmailClientError> {
+        tracing::info!(
+            recipient = recipient.as_ref(),
+            subject,
+            "email recorded in memory"
+        );
+        self.sent
+            .lock()
+            .map_err(|e| EmailClientError::Transport(e.to_string().into()))?
+            .push(SentEmail {
+                recipient: recipient.as_ref().to_owned(),
+                subject: subject.to_owned(),
+                html_content: html_content.to_owned(),
+                text_content: text_content.to_owned(),
+                headers: headers
+                    .iter()
+                    .map(|(name, value)| (name.to_string(), value.to_string()))
+                    .collect(),
+            });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn sent_emails_are_recorded_in_order() {
+        // Arrange
+        let email_client = InMemoryEmailClient::new();
+        let first = SubscriberEmail::parse("first@domain.com").unwrap();
+        let second = SubscriberEmail::parse("second@domain.com").unwrap();
+        // Act
+        email_client
+            .send_email(&first, "One", "<p>one</p>", "one")
+            .await
+            .unwrap();
+        email_client
+            .send_email(&second, "Two", "<p>two</p>", "two")
+            .await
+            .unwrap();
+        // Assert
+        let sent = email_client.sent_emails();
+        assert_eq!(sent.len(), 2);
+        assert_eq!(sent[0].recipient, "first@domain.com");
+        assert_eq!(sent[0].html_content, "<p>one</p>");
+        assert_eq!(sent[1].subject, "Two");
+        assert_eq!(sent[1].text_content, "two");
+    }
+}
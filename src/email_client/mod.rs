@@ -0,0 +1,61 @@
+mod in_memory;
+mod retry;
+mod smtp;
+mod unisender;
+pub use in_memory::{InMemoryEmailClient, SentEmail};
+pub use retry::RetryPolicy;
+pub use smtp::SmtpEmailClient;
+pub use unisender::{EmailClient, EmailSendError, SendOverrides};
+
+use crate::domain::SubscriberEmail;
+
+/// Delivers emails through one of the supported providers
+#[async_trait::async_trait]
+pub trait EmailSender: std::fmt::Debug + Send + Sync {
+    async fn send_email(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+    ) -> Result<(), EmailClientError> {
+        self.send_email_with_headers(recipient, subject, html_content, text_content, &[])
+            .await
+    }
+    /// Send an email carrying extra `(name, value)` headers, e.g. `List-Unsubscribe`
+    async fn send_email_with_headers(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        headers: &[(&str, &str)],
+    ) -> Result<(), EmailClientError>;
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum EmailClientError {
+    /// The provider could not be reached, including timeouts
+    #[error("failed to reach the email provider")]
+    Transport(#[source] Box<dyn std::error::Error + Send + Sync>),
+    /// The provider answered with a non-success HTTP status
+    #[error("email provider responded with {0}")]
+    Status(reqwest::StatusCode),
+    /// The provider accepted the request but refused to deliver the email
+    #[error("email provider rejected the email: {}", join_errors(.0))]
+    Rejected(Vec<EmailSendError>),
+    /// The provider response could not be decoded
+    #[error("failed to decode the email provider response")]
+    Decode(#[source] serde_json::Error),
+    /// The email could not be assembled from the given parts
+    #[error("invalid email: {0}")]
+    InvalidMessage(String),
+}
+
+fn join_errors(errors: &[EmailSendError]) -> String {
+    errors
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("; ")
+}
@@ -0,0 +1,116 @@
+use crate::configuration::RetrySettings;
+use crate::email_client::EmailClientError;
+use rand::Rng;
+use std::time::Duration;
+
+/// How many times and how often a failed delivery is attempted again
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    /// Total number of attempts, including the first one
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    /// Randomise each delay between half and the full backoff value
+    pub jitter: bool,
+    pub retryable_status_codes: Vec<u16>,
+}
+
+impl Default for RetryPolicy {
+    /// A single attempt, no retries
+    fn default() -> Self {
+        Self {
+            max_attempts: 1,
+            base_delay: Duration::ZERO,
+            max_delay: Duration::ZERO,
+            jitter: false,
+            retryable_status_codes: Vec::new(),
+        }
+    }
+}
+
+impl From<&RetrySettings> for RetryPolicy {
+    fn from(settings: &RetrySettings) -> Self {
+        Self {
+            max_attempts: settings.max_attempts.max(1),
+            base_delay: Duration::from_millis(settings.base_delay),
+            max_delay: Duration::from_millis(settings.max_delay),
+            jitter: settings.jitter,
+            retryable_status_codes: settings.retryable_status_codes.clone(),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Whether another attempt may succeed where this one failed
+    pub fn is_retryable(&self, error: &EmailClientError) -> bool {
+        match error {
+            EmailClientError::Transport(_) => true,
+            EmailClientError::Status(status) => {
+                self.retryable_status_codes.contains(&status.as_u16())
+            }
+            EmailClientError::Rejected(_)
+            | EmailClientError::Decode(_)
+            | EmailClientError::InvalidMessage(_) => false,
+        }
+    }
+    /// Delay to wait after the given failed attempt, counting from 1
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(31);
+        let backoff = self
+            .base_delay
+            .saturating_mul(2u32.pow(exponent))
+            .min(self.max_delay);
+        if self.jitter && !backoff.is_zero() {
+            let half = backoff / 2;
+            half + rand::thread_rng().gen_range(Duration::ZERO..=backoff - half)
+        } else {
+            backoff
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(jitter: bool) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 5,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(1000),
+            jitter,
+            retryable_status_codes: vec![429, 503],
+        }
+    }
+    #[test]
+    fn delay_doubles_with_every_attempt() {
+        let policy = policy(false);
+        assert_eq!(policy.delay(1), Duration::from_millis(100));
+        assert_eq!(policy.delay(2), Duration::from_millis(200));
+        assert_eq!(policy.delay(3), Duration::from_millis(400));
+    }
+    #[test]
+    fn delay_is_capped_at_max_delay() {
+        let policy = policy(false);
+        assert_eq!(policy.delay(5), Duration::from_millis(1000));
+        assert_eq!(policy.delay(u32::MAX), Duration::from_millis(1000));
+    }
+    #[test]
+    fn jittered_delay_stays_between_half_and_full_backoff() {
+        let policy = policy(true);
+        for _ in 0..100 {
+            let delay = policy.delay(3);
+            assert!(delay >= Duration::from_millis(200));
+            assert!(delay <= Duration::from_millis(400));
+        }
+    }
+    #[test]
+    fn only_configured_status_codes_are_retried() {
+        let policy = policy(false);
+        let status = |code| EmailClientError::Status(reqwest::StatusCode::from_u16(code).unwrap());
+        assert!(policy.is_retryable(&status(503)));
+        assert!(policy.is_retryable(&status(429)));
+        assert!(!policy.is_retryable(&status(400)));
+        assert!(!policy.is_retryable(&EmailClientError::Rejected(vec![])));
+    }
+}
@@ -0,0 +1,140 @@
+use crate::configuration::{SmtpSettings, SmtpTls};
+use crate::domain::SubscriberEmail;
+use crate::email_client::{EmailClientError, EmailSender};
+use lettre::message::header::{HeaderName, HeaderValue};
+use lettre::message::{Mailbox, MultiPart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+
+#[derive(Debug)]
+pub struct SmtpEmailClient {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    sender: SubscriberEmail,
+}
+
+impl SmtpEmailClient {
+    pub fn new(
+        settings: &SmtpSettings,
+        sender: SubscriberEmail,
+        timeout: std::time::Duration,
+    ) -> Result<Self, EmailClientError> {
+        let builder = match settings.tls {
+            SmtpTls::None => {
+                AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&settings.host)
+            }
+            SmtpTls::Starttls => {
+                AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&settings.host)
+                    .map_err(|e| EmailClientError::Transport(e.into()))?
+            }
+            SmtpTls::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&settings.host)
+                .map_err(|e| EmailClientError::Transport(e.into()))?,
+        };
+        let transport = builder
+            .port(settings.port)
+            .credentials(Credentials::new(
+                settings.username.clone(),
+                settings.password.clone(),
+            ))
+            .timeout(Some(timeout))
+            .build();
+        Ok(Self { transport, sender })
+    }
+    fn build_message(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        headers: &[(&str, &str)],
+    ) -> Result<Message, EmailClientError> {
+        let invalid = |e: &dyn std::fmt::Display| EmailClientError::InvalidMessage(e.to_string());
+        let from: Mailbox = self.sender.as_ref().parse().map_err(|e| invalid(&e))?;
+        let to: Mailbox = recipient.as_ref().parse().map_err(|e| invalid(&e))?;
+        let mut builder = Message::builder().from(from).to(to).subject(subject);
+        for (name, value) in headers {
+            let name = HeaderName::new_from_ascii(name.to_string()).map_err(|e| invalid(&e))?;
+            builder = builder.raw_header(HeaderValue::new(name, value.to_string()));
+        }
+        builder
+            .multipart(MultiPart::alternative_plain_html(
+                text_content.to_owned(),
+                html_content.to_owned(),
+            ))
+            .map_err(|e| invalid(&e))
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailSender for SmtpEmailClient {
+    async fn send_email_with_headers(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        headers: &[(&str, &str)],
+    ) -> Result<(), EmailClientError> {
+        let message =
+            self.build_message(recipient, subject, html_content, text_content, headers)?;
+        self.transport
+            .send(message)
+            .await
+            .map_err(|e| EmailClientError::Transport(e.into()))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Get a test instance of `SmtpEmailClient` that never connects anywhere
+    fn email_client() -> SmtpEmailClient {
+        let settings = SmtpSettings {
+            host: "localhost".to_string(),
+            port: 2525,
+            username: "user".to_string(),
+            password: "password".to_string(),
+            tls: SmtpTls::None,
+        };
+        let sender = SubscriberEmail::parse("sender@domain.com").unwrap();
+        SmtpEmailClient::new(&settings, sender, std::time::Duration::from_millis(200)).unwrap()
+    }
+    #[tokio::test]
+    async fn message_contains_both_plain_text_and_html_alternatives() {
+        // Arrange
+        let email_client = email_client();
+        let recipient = SubscriberEmail::parse("recipient@domain.com").unwrap();
+        // Act
+        let message = email_client
+            .build_message(&recipient, "Welcome", "<p>Hello</p>", "Hello", &[])
+            .unwrap();
+        // Assert
+        let formatted = String::from_utf8(message.formatted()).unwrap();
+        assert!(formatted.contains("From: sender@domain.com"));
+        assert!(formatted.contains("To: recipient@domain.com"));
+        assert!(formatted.contains("Subject: Welcome"));
+        assert!(formatted.contains("multipart/alternative"));
+        assert!(formatted.contains("text/plain"));
+        assert!(formatted.contains("text/html"));
+        assert!(formatted.contains("<p>Hello</p>"));
+    }
+    #[tokio::test]
+    async fn message_contains_the_extra_headers() {
+        // Arrange
+        let email_client = email_client();
+        let recipient = SubscriberEmail::parse("recipient@domain.com").unwrap();
+        let headers = [
+            ("List-Unsubscribe", "<https://domain.com/unsubscribe>"),
+            ("List-Unsubscribe-Post", "List-Unsubscribe=One-Click"),
+        ];
+        // Act
+        let message = email_client
+            .build_message(&recipient, "Welcome", "<p>Hello</p>", "Hello", &headers)
+            .unwrap();
+        // Assert
+        let formatted = String::from_utf8(message.formatted()).unwrap();
+        assert!(formatted.contains("List-Unsubscribe: <https://domain.com/unsubscribe>"));
+        assert!(formatted.contains("List-Unsubscribe-Post: List-Unsubscribe=One-Click"));
+    }
+}
@@ -0,0 +1,726 @@
+use crate::domain::SubscriberEmail;
+use crate::email_client::{EmailClientError, EmailSender, RetryPolicy};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug)]
+pub struct EmailClient {
+    base_url: String,
+    client: Client,
+    sender: SubscriberEmail,
+    sender_name: String,
+    list_id: String,
+    api_key: String,
+    retry_policy: RetryPolicy,
+}
+
+/// Per-send replacements for the sender name and list configured on [`EmailClient`]
+#[derive(Debug, Default, Clone)]
+pub struct SendOverrides {
+    pub sender_name: Option<String>,
+    pub list_id: Option<String>,
+}
+
+impl EmailClient {
+    pub fn new(
+        base_url: &str,
+        sender: SubscriberEmail,
+        sender_name: &str,
+        list_id: &str,
+        api_key: &str,
+        timeout: std::time::Duration,
+    ) -> Result<Self, EmailClientError> {
+        let client = Client::builder()
+            .timeout(timeout)
+            .build()
+            .map_err(|e| EmailClientError::Transport(e.into()))?;
+        Ok(Self {
+            base_url: base_url.to_owned(),
+            client,
+            sender,
+            sender_name: sender_name.to_owned(),
+            list_id: list_id.to_owned(),
+            api_key: api_key.to_owned(),
+            retry_policy: RetryPolicy::default(),
+        })
+    }
+    /// Retry failed deliveries according to `retry_policy`
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+    /// Send an email replacing the configured sender name and list where `overrides` says so
+    pub async fn send_email_with_overrides(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        overrides: &SendOverrides,
+    ) -> Result<(), EmailClientError> {
+        self.send(
+            recipient,
+            subject,
+            html_content,
+            text_content,
+            &[],
+            overrides,
+        )
+        .await
+    }
+    async fn send(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        headers: &[(&str, &str)],
+        overrides: &SendOverrides,
+    ) -> Result<(), EmailClientError> {
+        let uri = format!("{}/ru/api/sendEmail", self.base_url);
+        let sender_name = overrides
+            .sender_name
+            .as_deref()
+            .unwrap_or(&self.sender_name);
+        let list_id = overrides.list_id.as_deref().unwrap_or(&self.list_id);
+        let params = RequestParams::builder(&self.api_key)
+            .sender_name(sender_name)
+            .list_id(list_id)
+            .sender_email(self.sender.as_ref())
+            .email(recipient.as_ref())
+            .subject(subject)
+            .body(html_content)
+            .text_body(text_content)
+            .headers(headers);
+        let mut attempt = 1;
+        loop {
+            match self.post(&uri, &params).await {
+                Err(e)
+                    if attempt < self.retry_policy.max_attempts
+                        && self.retry_policy.is_retryable(&e) =>
+                {
+                    let delay = self.retry_policy.delay(attempt);
+                    tracing::warn!(
+                        error = %e,
+                        attempt,
+                        delay_ms = delay.as_millis() as u64,
+                        "email delivery failed, retrying"
+                    );
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                outcome => return outcome,
+            }
+        }
+    }
+    async fn post(&self, uri: &str, params: &RequestParams) -> Result<(), EmailClientError> {
+        let response = self
+            .client
+            .post(uri)
+            .form(params)
+            .send()
+            .await
+            .map_err(|e| EmailClientError::Transport(e.into()))?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(EmailClientError::Status(status));
+        }
+        let body = response
+            .text()
+            .await
+            .map_err(|e| EmailClientError::Transport(e.into()))?;
+        let result = serde_json::from_str::<EmailSendResponse>(&body)
+            .map_err(EmailClientError::Decode)?
+            .result;
+        match result.errors {
+            Some(errors) if !errors.is_empty() => Err(EmailClientError::Rejected(errors)),
+            _ => {
+                tracing::info!(
+                    "index: {index:?}; email_id: {email_id:?}",
+                    index = result.index,
+                    email_id = result.email_id
+                );
+                Ok(())
+            }
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailSender for EmailClient {
+    async fn send_email_with_headers(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        headers: &[(&str, &str)],
+    ) -> Result<(), EmailClientError> {
+        self.send(
+            recipient,
+            subject,
+            html_content,
+            text_content,
+            headers,
+            &SendOverrides::default(),
+        )
+        .await
+    }
+}
+
+#[derive(Serialize, Default)]
+struct RequestParams {
+    format: String,
+    api_key: String,
+    email: String,
+    sender_name: String,
+    sender_email: String,
+    subject: String,
+    body: String,
+    text_body: String,
+    list_id: String,
+    /// Extra headers, one `Name: value` per line
+    #[serde(skip_serializing_if = "String::is_empty")]
+    headers: String,
+}
+impl RequestParams {
+    fn builder(api_key: &str) -> Self {
+        RequestParams {
+            format: "json".to_string(),
+            api_key: api_key.to_owned(),
+            ..Self::default()
+        }
+    }
+    fn sender_name(mut self, sender_name: &str) -> Self {
+        self.sender_name = sender_name.to_string();
+        self
+    }
+    fn list_id(mut self, list_id: &str) -> Self {
+        self.list_id = list_id.to_string();
+        self
+    }
+    fn sender_email(mut self, sender_email: &str) -> Self {
+        self.sender_email = sender_email.to_string();
+        self
+    }
+    fn email(mut self, email: &str) -> Self {
+        self.email = email.to_string();
+        self
+    }
+    fn subject(mut self, subject: &str) -> Self {
+        self.subject = subject.to_string();
+        self
+    }
+    fn body(mut self, body: &str) -> Self {
+        self.body = body.to_string();
+        self
+    }
+    fn text_body(mut self, text_body: &str) -> Self {
+        self.text_body = text_body.to_string();
+        self
+    }
+    fn headers(mut self, headers: &[(&str, &str)]) -> Self {
+        self.headers = headers
+            .iter()
+            .map(|(name, value)| format!("{name}: {value}"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        self
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct EmailSendResponse {
+    result: EmailSendResult,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct EmailSendResult {
+    index: Option<i64>,
+    email_id: String,
+    errors: Option<Vec<EmailSendError>>,
+}
+
+/// Error reported by Unisender for a single recipient
+#[derive(Deserialize, Debug, Clone)]
+pub struct EmailSendError {
+    pub code: String,
+    pub message: String,
+}
+impl std::fmt::Display for EmailSendError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.code, self.message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fake::faker::internet::en::SafeEmail;
+    use fake::faker::lorem::en::{Paragraph, Sentence};
+    use fake::{Fake, Faker};
+    use serde_json::json;
+    use wiremock::matchers::{any, header, method, path};
+    use wiremock::{Mock, MockServer, Request, ResponseTemplate};
+
+    /// Matches requests whose form-encoded body contains `key` set to `value`
+    struct FormParamMatcher {
+        key: String,
+        value: String,
+    }
+    impl wiremock::Match for FormParamMatcher {
+        fn matches(&self, request: &Request) -> bool {
+            form_urlencoded::parse(&request.body)
+                .any(|(key, value)| key == self.key.as_str() && value == self.value.as_str())
+        }
+    }
+    fn form_param(key: &str, value: &str) -> FormParamMatcher {
+        FormParamMatcher {
+            key: key.to_owned(),
+            value: value.to_owned(),
+        }
+    }
+
+    /// Generate a random email subject
+    fn subject() -> String {
+        Sentence(1..2).fake()
+    }
+    /// Generate a random email content
+    fn content() -> String {
+        Paragraph(1..10).fake()
+    }
+    /// Generate a random subscriber email
+    fn email() -> SubscriberEmail {
+        let addr: String = SafeEmail().fake();
+        SubscriberEmail::parse(&addr).unwrap()
+    }
+    /// Get a test instance of`EmailClient`
+    fn email_client(base_url: String) -> EmailClient {
+        let api_key: String = Faker.fake();
+        let timeout = std::time::Duration::from_millis(200);
+        EmailClient::new(&base_url, email(), "zero2prod", "1", &api_key, timeout).unwrap()
+    }
+    /// Get a test instance of `EmailClient` retrying 503s and timeouts without delay
+    fn retrying_email_client(base_url: String, max_attempts: u32) -> EmailClient {
+        email_client(base_url).with_retry_policy(RetryPolicy {
+            max_attempts,
+            base_delay: std::time::Duration::from_millis(1),
+            max_delay: std::time::Duration::from_millis(10),
+            jitter: true,
+            retryable_status_codes: vec![503],
+        })
+    }
+    #[tokio::test]
+    async fn send_email_fires_a_request_to_base_url_and_succeed_when_200() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        let subscriber_email = email();
+        let subject = subject();
+        let html_content = content();
+        let text_content = content();
+        let body = json!({
+            "result": {
+              "email_id": "some id"
+            }
+        });
+        Mock::given(path("/ru/api/sendEmail"))
+            .and(method("POST"))
+            .and(header("Content-Type", "application/x-www-form-urlencoded"))
+            .and(form_param("format", "json"))
+            .and(form_param("email", subscriber_email.as_ref()))
+            .and(form_param("list_id", "1"))
+            .and(form_param("sender_name", "zero2prod"))
+            .and(form_param("subject", &subject))
+            .and(form_param("body", &html_content))
+            .and(form_param("text_body", &text_content))
+            .respond_with(ResponseTemplate::new(200).set_body_json(body))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        // Act
+        let result = email_client
+            .send_email(&subscriber_email, &subject, &html_content, &text_content)
+            .await;
+        // Assert
+        assert!(result.is_ok());
+    }
+    #[tokio::test]
+    async fn send_email_fails_if_the_server_returns_500() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        let subscriber_email = email();
+        let subject = subject();
+        let content = content();
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        // Act
+        let outcome = email_client
+            .send_email(&subscriber_email, &subject, &content, &content)
+            .await;
+        // Assert
+        assert!(matches!(
+            outcome,
+            Err(EmailClientError::Status(status)) if status.as_u16() == 500
+        ));
+    }
+    #[tokio::test]
+    async fn send_email_times_out_if_the_server_takes_too_long() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        let subscriber_email = email();
+        let subject = subject();
+        let content = content();
+        let response = ResponseTemplate::new(200).set_delay(std::time::Duration::from_secs(180));
+        Mock::given(any())
+            .respond_with(response)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        // Act
+        let outcome = email_client
+            .send_email(&subscriber_email, &subject, &content, &content)
+            .await;
+        // Assert
+        assert!(matches!(outcome, Err(EmailClientError::Transport(_))));
+    }
+    #[tokio::test]
+    async fn send_email_does_not_put_the_api_key_in_the_query_string() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        let body = json!({
+            "result": {
+              "email_id": "some id"
+            }
+        });
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_body_json(body))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        // Act
+        let _ = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+        // Assert
+        let request = &mock_server.received_requests().await.unwrap()[0];
+        assert!(request.url.query().is_none());
+        assert!(form_urlencoded::parse(&request.body).any(|(key, _)| key == "api_key"));
+    }
+    #[tokio::test]
+    async fn send_email_fails_with_a_status_error_if_the_server_returns_400() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(400))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        // Act
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+        // Assert
+        assert!(matches!(
+            outcome,
+            Err(EmailClientError::Status(status)) if status.as_u16() == 400
+        ));
+    }
+    #[tokio::test]
+    async fn send_email_fails_if_the_provider_reports_errors_in_a_200_response() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        let body = json!({
+            "result": {
+              "email_id": "some id",
+              "errors": [
+                {
+                  "code": "invalid_arg",
+                  "message": "Email is not valid"
+                }
+              ]
+            }
+        });
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_body_json(body))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        // Act
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+        // Assert
+        match outcome {
+            Err(EmailClientError::Rejected(errors)) => {
+                assert_eq!(errors.len(), 1);
+                assert_eq!(errors[0].code, "invalid_arg");
+                assert_eq!(errors[0].message, "Email is not valid");
+            }
+            other => panic!("expected a rejection, got {other:?}"),
+        }
+    }
+    #[tokio::test]
+    async fn send_email_succeeds_if_the_errors_list_is_empty() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        let body = json!({
+            "result": {
+              "email_id": "some id",
+              "errors": []
+            }
+        });
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_body_json(body))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        // Act
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+        // Assert
+        assert!(outcome.is_ok());
+    }
+    #[tokio::test]
+    async fn send_email_fails_with_a_decode_error_if_the_response_is_not_json() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_body_string("not json"))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        // Act
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+        // Assert
+        assert!(matches!(outcome, Err(EmailClientError::Decode(_))));
+    }
+    #[tokio::test]
+    async fn send_email_uses_the_configured_sender_name_and_list_id() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let api_key: String = Faker.fake();
+        let timeout = std::time::Duration::from_millis(200);
+        let email_client = EmailClient::new(
+            &mock_server.uri(),
+            email(),
+            "Tenant Newsletter",
+            "42",
+            &api_key,
+            timeout,
+        )
+        .unwrap();
+        let body = json!({
+            "result": {
+              "email_id": "some id"
+            }
+        });
+        Mock::given(path("/ru/api/sendEmail"))
+            .and(form_param("sender_name", "Tenant Newsletter"))
+            .and(form_param("list_id", "42"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(body))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        // Act
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+        // Assert
+        assert!(outcome.is_ok());
+    }
+    #[tokio::test]
+    async fn send_email_with_overrides_replaces_the_configured_defaults() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        let body = json!({
+            "result": {
+              "email_id": "some id"
+            }
+        });
+        Mock::given(path("/ru/api/sendEmail"))
+            .and(form_param("sender_name", "Weekly Digest"))
+            .and(form_param("list_id", "7"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(body))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        let overrides = SendOverrides {
+            sender_name: Some("Weekly Digest".to_string()),
+            list_id: Some("7".to_string()),
+        };
+        // Act
+        let outcome = email_client
+            .send_email_with_overrides(&email(), &subject(), &content(), &content(), &overrides)
+            .await;
+        // Assert
+        assert!(outcome.is_ok());
+    }
+    #[tokio::test]
+    async fn send_email_with_headers_sends_one_header_per_line() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        let body = json!({
+            "result": {
+              "email_id": "some id"
+            }
+        });
+        Mock::given(path("/ru/api/sendEmail"))
+            .and(form_param(
+                "headers",
+                "List-Unsubscribe: <https://domain.com/unsubscribe>\n\
+                List-Unsubscribe-Post: List-Unsubscribe=One-Click",
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(body))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        let headers = [
+            ("List-Unsubscribe", "<https://domain.com/unsubscribe>"),
+            ("List-Unsubscribe-Post", "List-Unsubscribe=One-Click"),
+        ];
+        // Act
+        let outcome = email_client
+            .send_email_with_headers(&email(), &subject(), &content(), &content(), &headers)
+            .await;
+        // Assert
+        assert!(outcome.is_ok());
+    }
+    #[tokio::test]
+    async fn send_email_with_partial_overrides_keeps_the_other_default() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        let body = json!({
+            "result": {
+              "email_id": "some id"
+            }
+        });
+        Mock::given(path("/ru/api/sendEmail"))
+            .and(form_param("sender_name", "zero2prod"))
+            .and(form_param("list_id", "7"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(body))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        let overrides = SendOverrides {
+            list_id: Some("7".to_string()),
+            ..SendOverrides::default()
+        };
+        // Act
+        let outcome = email_client
+            .send_email_with_overrides(&email(), &subject(), &content(), &content(), &overrides)
+            .await;
+        // Assert
+        assert!(outcome.is_ok());
+    }
+    #[tokio::test]
+    async fn send_email_retries_until_the_server_succeeds() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = retrying_email_client(mock_server.uri(), 3);
+        let body = json!({
+            "result": {
+              "email_id": "some id"
+            }
+        });
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(2)
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_body_json(body))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        // Act
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+        // Assert
+        assert!(outcome.is_ok());
+    }
+    #[tokio::test]
+    async fn send_email_gives_up_after_max_attempts() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = retrying_email_client(mock_server.uri(), 3);
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(503))
+            .expect(3)
+            .mount(&mock_server)
+            .await;
+        // Act
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+        // Assert
+        assert!(matches!(
+            outcome,
+            Err(EmailClientError::Status(status)) if status.as_u16() == 503
+        ));
+    }
+    #[tokio::test]
+    async fn send_email_does_not_retry_non_retryable_statuses() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = retrying_email_client(mock_server.uri(), 3);
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(400))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        // Act
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+        // Assert
+        assert!(outcome.is_err());
+    }
+    #[tokio::test]
+    async fn send_email_retries_after_a_timeout() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = retrying_email_client(mock_server.uri(), 2);
+        let body = json!({
+            "result": {
+              "email_id": "some id"
+            }
+        });
+        let slow = ResponseTemplate::new(200).set_delay(std::time::Duration::from_secs(180));
+        Mock::given(any())
+            .respond_with(slow)
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_body_json(body))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        // Act
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+        // Assert
+        assert!(outcome.is_ok());
+    }
+}
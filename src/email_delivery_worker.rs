@@ -0,0 +1,282 @@
+use crate::configuration::DeliveryWorkerSettings;
+use crate::domain::SubscriberEmail;
+use crate::email_client::{EmailSender, RetryPolicy};
+use chrono::Utc;
+use sqlx::{PgPool, Postgres, Transaction};
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Add an email to the outbound queue as part of a larger transaction.
+///
+/// Every email carries a footer with the unsubscribe link of its recipient, which is also
+/// sent as the `List-Unsubscribe` header.
+#[tracing::instrument(
+    name = "Enqueue an email for delivery",
+    skip(
+        transaction,
+        recipient,
+        subject,
+        html_content,
+        text_content,
+        unsubscribe_url
+    )
+)]
+pub async fn enqueue_email(
+    transaction: &mut Transaction<'_, Postgres>,
+    recipient: &SubscriberEmail,
+    subject: &str,
+    html_content: &str,
+    text_content: &str,
+    unsubscribe_url: &str,
+) -> Result<Uuid, sqlx::Error> {
+    let email_id = Uuid::new_v4();
+    let html_content = format!(
+        "{html_content}<br /><br />\
+        <small>Don't want these emails anymore? \
+        <a href=\"{unsubscribe_url}\">Unsubscribe</a>.</small>"
+    );
+    let text_content = format!(
+        "{text_content}\n\nDon't want these emails anymore? Unsubscribe: {unsubscribe_url}"
+    );
+    sqlx::query!(
+        r#"
+INSERT INTO email_delivery_queue (
+    id, recipient_email, subject, html_content, text_content, unsubscribe_url
+)
+VALUES ($1, $2, $3, $4, $5, $6)
+"#,
+        email_id,
+        recipient.as_ref(),
+        subject,
+        html_content,
+        text_content,
+        unsubscribe_url
+    )
+    .execute(&mut **transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {e:?}");
+        e
+    })?;
+    Ok(email_id)
+}
+
+/// Cancel the emails still waiting for delivery to `recipient`, once they should no longer
+/// be sent, returning how many were cancelled
+#[tracing::instrument(name = "Cancel pending emails", skip(transaction, recipient))]
+pub async fn cancel_pending_emails(
+    transaction: &mut Transaction<'_, Postgres>,
+    recipient: &str,
+    reason: &str,
+) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+UPDATE email_delivery_queue
+SET status = 'cancelled', last_error = $2, completed_at = now()
+WHERE recipient_email = $1 AND status = 'pending'
+"#,
+        recipient,
+        reason
+    )
+    .execute(&mut **transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {e:?}");
+        e
+    })?;
+    Ok(result.rows_affected())
+}
+
+pub enum ExecutionOutcome {
+    TaskCompleted,
+    EmptyQueue,
+}
+
+/// Delivers queued emails in the background
+#[derive(Debug)]
+pub struct EmailDeliveryWorker {
+    pool: PgPool,
+    email_client: Arc<dyn EmailSender>,
+    retry_policy: RetryPolicy,
+    poll_interval: Duration,
+}
+
+impl EmailDeliveryWorker {
+    pub fn new(
+        pool: PgPool,
+        email_client: Arc<dyn EmailSender>,
+        settings: &DeliveryWorkerSettings,
+    ) -> Self {
+        Self {
+            pool,
+            email_client,
+            retry_policy: RetryPolicy::from(&settings.retry),
+            poll_interval: settings.poll_interval(),
+        }
+    }
+    pub async fn run_until_stopped(self) {
+        loop {
+            match self.try_execute_task().await {
+                Ok(ExecutionOutcome::EmptyQueue) => tokio::time::sleep(self.poll_interval).await,
+                Ok(ExecutionOutcome::TaskCompleted) => {}
+                Err(_) => tokio::time::sleep(Duration::from_secs(1)).await,
+            }
+        }
+    }
+    /// Attempt the delivery of the oldest due email, if any
+    #[tracing::instrument(
+        skip_all,
+        fields(email_id = tracing::field::Empty, recipient = tracing::field::Empty),
+        err
+    )]
+    pub async fn try_execute_task(&self) -> Result<ExecutionOutcome, sqlx::Error> {
+        let mut transaction = self.pool.begin().await?;
+        let Some(task) = dequeue_task(&mut transaction).await? else {
+            return Ok(ExecutionOutcome::EmptyQueue);
+        };
+        tracing::Span::current()
+            .record("email_id", tracing::field::display(task.id))
+            .record("recipient", tracing::field::display(&task.recipient_email));
+        let attempt = task.n_attempts as u32 + 1;
+        let list_unsubscribe = task.unsubscribe_url.as_ref().map(|url| format!("<{url}>"));
+        let headers = match &list_unsubscribe {
+            Some(list_unsubscribe) => vec![
+                ("List-Unsubscribe", list_unsubscribe.as_str()),
+                ("List-Unsubscribe-Post", "List-Unsubscribe=One-Click"),
+            ],
+            None => vec![],
+        };
+        let outcome = match SubscriberEmail::parse(&task.recipient_email) {
+            Ok(recipient) => self
+                .email_client
+                .send_email_with_headers(
+                    &recipient,
+                    &task.subject,
+                    &task.html_content,
+                    &task.text_content,
+                    &headers,
+                )
+                .await
+                .map_err(|e| {
+                    let retry = self.retry_policy.is_retryable(&e)
+                        && attempt < self.retry_policy.max_attempts;
+                    (error_chain(&e), retry)
+                }),
+            Err(e) => Err((e, false)),
+        };
+        match outcome {
+            Ok(()) => mark_sent(&mut transaction, task.id).await?,
+            Err((error, true)) => {
+                let delay = self.retry_policy.delay(attempt);
+                tracing::warn!(error, attempt, "email delivery failed, rescheduling");
+                reschedule(&mut transaction, task.id, &error, delay).await?;
+            }
+            Err((error, false)) => {
+                tracing::error!(error, attempt, "email delivery failed permanently");
+                mark_failed(&mut transaction, task.id, &error).await?;
+            }
+        }
+        transaction.commit().await?;
+        Ok(ExecutionOutcome::TaskCompleted)
+    }
+}
+
+/// Render an error together with all of its sources
+fn error_chain(e: &dyn std::error::Error) -> String {
+    let mut message = e.to_string();
+    let mut current = e.source();
+    while let Some(cause) = current {
+        message.push_str(&format!(": {cause}"));
+        current = cause.source();
+    }
+    message
+}
+
+struct QueuedEmail {
+    id: Uuid,
+    recipient_email: String,
+    subject: String,
+    html_content: String,
+    text_content: String,
+    /// Only missing for emails queued before unsubscribe links existed
+    unsubscribe_url: Option<String>,
+    n_attempts: i32,
+}
+
+async fn dequeue_task(
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<Option<QueuedEmail>, sqlx::Error> {
+    sqlx::query_as!(
+        QueuedEmail,
+        r#"
+SELECT id, recipient_email, subject, html_content, text_content, unsubscribe_url, n_attempts
+FROM email_delivery_queue
+WHERE status = 'pending' AND execute_after <= now()
+ORDER BY execute_after
+FOR UPDATE SKIP LOCKED
+LIMIT 1
+"#
+    )
+    .fetch_optional(&mut **transaction)
+    .await
+}
+
+async fn mark_sent(
+    transaction: &mut Transaction<'_, Postgres>,
+    email_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+UPDATE email_delivery_queue
+SET status = 'sent', n_attempts = n_attempts + 1, last_error = NULL, completed_at = now()
+WHERE id = $1
+"#,
+        email_id
+    )
+    .execute(&mut **transaction)
+    .await?;
+    Ok(())
+}
+
+async fn reschedule(
+    transaction: &mut Transaction<'_, Postgres>,
+    email_id: Uuid,
+    error: &str,
+    delay: Duration,
+) -> Result<(), sqlx::Error> {
+    let execute_after =
+        Utc::now() + chrono::Duration::from_std(delay).unwrap_or_else(|_| chrono::Duration::zero());
+    sqlx::query!(
+        r#"
+UPDATE email_delivery_queue
+SET n_attempts = n_attempts + 1, last_error = $2, execute_after = $3
+WHERE id = $1
+"#,
+        email_id,
+        error,
+        execute_after
+    )
+    .execute(&mut **transaction)
+    .await?;
+    Ok(())
+}
+
+async fn mark_failed(
+    transaction: &mut Transaction<'_, Postgres>,
+    email_id: Uuid,
+    error: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+UPDATE email_delivery_queue
+SET status = 'failed', n_attempts = n_attempts + 1, last_error = $2, completed_at = now()
+WHERE id = $1
+"#,
+        email_id,
+        error
+    )
+    .execute(&mut **transaction)
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,46 @@
+/// Client-provided key identifying the retries of a single request
+#[derive(Debug)]
+pub struct IdempotencyKey(String);
+
+impl IdempotencyKey {
+    const MAX_LENGTH: usize = 50;
+}
+
+impl TryFrom<String> for IdempotencyKey {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        if s.trim().is_empty() {
+            return Err("The idempotency key cannot be empty".into());
+        }
+        if s.len() > Self::MAX_LENGTH {
+            return Err(format!(
+                "The idempotency key must be shorter than {} characters",
+                Self::MAX_LENGTH
+            ));
+        }
+        Ok(Self(s))
+    }
+}
+
+impl AsRef<str> for IdempotencyKey {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::IdempotencyKey;
+
+    #[test]
+    fn empty_keys_are_rejected() {
+        assert!(IdempotencyKey::try_from("".to_string()).is_err());
+        assert!(IdempotencyKey::try_from("  ".to_string()).is_err());
+    }
+    #[test]
+    fn keys_longer_than_50_characters_are_rejected() {
+        assert!(IdempotencyKey::try_from("a".repeat(51)).is_err());
+        assert!(IdempotencyKey::try_from("a".repeat(50)).is_ok());
+    }
+}
@@ -0,0 +1,109 @@
+mod key;
+mod persistence;
+pub use key::IdempotencyKey;
+pub use persistence::{get_saved_response, save_response, try_processing, NextAction};
+
+use crate::authentication::{AuthenticatedUser, UserId};
+use crate::utils::e500;
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::error::ErrorBadRequest;
+use actix_web::middleware::Next;
+use actix_web::{web, HttpMessage, HttpResponse};
+use anyhow::Context;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+
+/// Middleware replaying the saved response of requests repeating an `Idempotency-Key`,
+/// for use with [`actix_web::middleware::from_fn`].
+///
+/// Keys are scoped to the authenticated operator, so it must run after the authentication
+/// middleware of the scope. Requests without the header are processed as usual, and
+/// server errors are not saved so that the client can retry them. A key reused for a
+/// different request is rejected with a 422, as anonymous callers share the same keys.
+pub async fn idempotency(
+    mut request: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let Some(header_value) = request.headers().get(IDEMPOTENCY_KEY_HEADER) else {
+        return next
+            .call(request)
+            .await
+            .map(ServiceResponse::map_into_boxed_body);
+    };
+    let idempotency_key: IdempotencyKey = header_value
+        .to_str()
+        .map_err(ErrorBadRequest)?
+        .to_owned()
+        .try_into()
+        .map_err(ErrorBadRequest)?;
+    let caller = caller(&request);
+    let request_hash = hash_request(&mut request).await?;
+    let pool = request
+        .app_data::<web::Data<PgPool>>()
+        .cloned()
+        .context("No database pool registered in the application")
+        .map_err(e500)?;
+    let transaction = match try_processing(&pool, &idempotency_key, &caller, &request_hash)
+        .await
+        .map_err(e500)?
+    {
+        NextAction::StartProcessing(transaction) => transaction,
+        NextAction::ReturnSavedResponse(saved_response) => {
+            tracing::info!(idempotency_key = ?idempotency_key, "Replaying a saved response");
+            return Ok(request.into_response(saved_response));
+        }
+        NextAction::RejectKeyReuse => {
+            tracing::warn!(idempotency_key = ?idempotency_key, "Idempotency key reused");
+            let response = HttpResponse::UnprocessableEntity()
+                .body("This Idempotency-Key was already used for a different request.");
+            return Ok(request.into_response(response));
+        }
+    };
+    let (request, response) = next.call(request).await?.into_parts();
+    if response.status().is_server_error() {
+        // Dropping the transaction releases the key for the next attempt
+        return Ok(ServiceResponse::new(
+            request,
+            response.map_into_boxed_body(),
+        ));
+    }
+    let response = save_response(
+        transaction,
+        &idempotency_key,
+        &caller,
+        response.map_into_boxed_body(),
+    )
+    .await
+    .map_err(e500)?;
+    Ok(ServiceResponse::new(request, response))
+}
+
+/// SHA-256 of the method, path and body of the request, whose body is put back for the handler
+async fn hash_request(request: &mut ServiceRequest) -> Result<String, actix_web::Error> {
+    let body = request.extract::<web::Bytes>().await?;
+    let mut hasher = Sha256::new();
+    hasher.update(request.method().as_str());
+    hasher.update(b" ");
+    hasher.update(request.path());
+    hasher.update(b"\n");
+    hasher.update(&body);
+    let (_, mut payload) = actix_http::h1::Payload::create(true);
+    payload.unread_data(body);
+    request.set_payload(payload.into());
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Identity the keys are scoped to
+fn caller(request: &ServiceRequest) -> String {
+    let extensions = request.extensions();
+    if let Some(user) = extensions.get::<AuthenticatedUser>() {
+        user.user_id.to_string()
+    } else if let Some(user_id) = extensions.get::<UserId>() {
+        user_id.to_string()
+    } else {
+        "anonymous".to_string()
+    }
+}
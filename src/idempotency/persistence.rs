@@ -0,0 +1,146 @@
+use super::IdempotencyKey;
+use actix_web::body::to_bytes;
+use actix_web::http::StatusCode;
+use actix_web::HttpResponse;
+use sqlx::{PgPool, Postgres, Transaction};
+
+#[derive(Debug, sqlx::Type)]
+#[sqlx(type_name = "header_pair")]
+struct HeaderPairRecord {
+    name: String,
+    value: Vec<u8>,
+}
+
+/// Response saved for an earlier request with the same key, if any
+pub async fn get_saved_response(
+    pool: &PgPool,
+    idempotency_key: &IdempotencyKey,
+    caller: &str,
+) -> Result<Option<HttpResponse>, anyhow::Error> {
+    let saved_response = sqlx::query!(
+        r#"
+SELECT
+    response_status_code as "response_status_code!",
+    response_headers as "response_headers!: Vec<HeaderPairRecord>",
+    response_body as "response_body!"
+FROM idempotency
+WHERE caller = $1 AND idempotency_key = $2
+"#,
+        caller,
+        idempotency_key.as_ref()
+    )
+    .fetch_optional(pool)
+    .await?;
+    let Some(r) = saved_response else {
+        return Ok(None);
+    };
+    let status_code = StatusCode::from_u16(r.response_status_code.try_into()?)?;
+    let mut response = HttpResponse::build(status_code);
+    for HeaderPairRecord { name, value } in r.response_headers {
+        response.append_header((name, value));
+    }
+    Ok(Some(response.body(r.response_body)))
+}
+
+pub enum NextAction {
+    /// First request with this key, handle it and save the response in the transaction
+    StartProcessing(Transaction<'static, Postgres>),
+    /// Repeated request, replay the saved response
+    ReturnSavedResponse(HttpResponse),
+    /// The key was used for a different request, which must not be replayed to this one
+    RejectKeyReuse,
+}
+
+/// Claim the key for this request or fetch the response saved for it.
+///
+/// A concurrent request with the same key blocks on the insert until the first one
+/// commits its response, and then replays it if it is the same request, as told by `request_hash`.
+pub async fn try_processing(
+    pool: &PgPool,
+    idempotency_key: &IdempotencyKey,
+    caller: &str,
+    request_hash: &str,
+) -> Result<NextAction, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    let n_inserted_rows = sqlx::query!(
+        r#"
+INSERT INTO idempotency (caller, idempotency_key, request_hash, created_at)
+VALUES ($1, $2, $3, now())
+ON CONFLICT DO NOTHING
+"#,
+        caller,
+        idempotency_key.as_ref(),
+        request_hash
+    )
+    .execute(&mut *transaction)
+    .await?
+    .rows_affected();
+    if n_inserted_rows > 0 {
+        return Ok(NextAction::StartProcessing(transaction));
+    }
+    if !is_same_request(pool, idempotency_key, caller, request_hash).await? {
+        return Ok(NextAction::RejectKeyReuse);
+    }
+    let saved_response = get_saved_response(pool, idempotency_key, caller)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("We expected a saved response, we didn't find it"))?;
+    Ok(NextAction::ReturnSavedResponse(saved_response))
+}
+
+async fn is_same_request(
+    pool: &PgPool,
+    idempotency_key: &IdempotencyKey,
+    caller: &str,
+    request_hash: &str,
+) -> Result<bool, anyhow::Error> {
+    let saved_hash = sqlx::query!(
+        r#"
+SELECT request_hash
+FROM idempotency
+WHERE caller = $1 AND idempotency_key = $2
+"#,
+        caller,
+        idempotency_key.as_ref()
+    )
+    .fetch_one(pool)
+    .await?
+    .request_hash;
+    // Keys saved before hashes were recorded are trusted
+    Ok(saved_hash.is_none_or(|saved_hash| saved_hash == request_hash))
+}
+
+/// Save the response under the claimed key and commit, returning the response to send
+pub async fn save_response(
+    mut transaction: Transaction<'static, Postgres>,
+    idempotency_key: &IdempotencyKey,
+    caller: &str,
+    http_response: HttpResponse,
+) -> Result<HttpResponse, anyhow::Error> {
+    let (response_head, body) = http_response.into_parts();
+    let body = to_bytes(body).await.map_err(|e| anyhow::anyhow!("{e}"))?;
+    let status_code = response_head.status().as_u16() as i16;
+    let headers = response_head
+        .headers()
+        .iter()
+        .map(|(name, value)| HeaderPairRecord {
+            name: name.as_str().to_owned(),
+            value: value.as_bytes().to_owned(),
+        })
+        .collect::<Vec<_>>();
+    sqlx::query!(
+        r#"
+UPDATE idempotency
+SET response_status_code = $3, response_headers = $4, response_body = $5
+WHERE caller = $1 AND idempotency_key = $2
+"#,
+        caller,
+        idempotency_key.as_ref(),
+        status_code,
+        headers as _,
+        body.as_ref()
+    )
+    .execute(&mut *transaction)
+    .await?;
+    transaction.commit().await?;
+    Ok(response_head.set_body(body).map_into_boxed_body())
+}
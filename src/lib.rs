@@ -1,6 +1,13 @@
+pub mod authentication;
 pub mod configuration;
 pub mod domain;
 pub mod email_client;
+pub mod email_delivery_worker;
+pub mod idempotency;
+pub mod pagination;
 pub mod routes;
+pub mod session_state;
 pub mod startup;
+pub mod subscription_cleanup;
 pub mod telemetry;
+pub mod utils;
@@ -0,0 +1,246 @@
+//! Keyset pagination over lists ordered by `(timestamp, id)`.
+//!
+//! Each page is fetched with a predicate on the key of the last row of the previous page rather
+//! than with an `OFFSET`, so that pages stay cheap deep into a large table and rows inserted
+//! while a client is paging neither shift nor repeat the rows it has yet to see:
+//!
+//! ```sql
+//! WHERE ($1::timestamptz IS NULL OR (subscribed_at, id) > ($1, $2))
+//! ORDER BY subscribed_at, id
+//! LIMIT $3
+//! ```
+//!
+//! with the bindings of [`PageRequest`].
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+pub const DEFAULT_PAGE_SIZE: i64 = 50;
+pub const MAX_PAGE_SIZE: i64 = 200;
+
+/// Invalid pagination parameter of a list request
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum PaginationError {
+    #[error("'{0}' is not a valid cursor")]
+    InvalidCursor(String),
+    #[error("The page size must be between 1 and {MAX_PAGE_SIZE}")]
+    InvalidPageSize,
+}
+
+impl PaginationError {
+    /// Name of the query parameter at fault
+    pub fn parameter(&self) -> &'static str {
+        match self {
+            PaginationError::InvalidCursor(_) => "cursor",
+            PaginationError::InvalidPageSize => "page_size",
+        }
+    }
+}
+
+/// Key of the last row of a page, handed to clients as an opaque string
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cursor {
+    pub timestamp: DateTime<Utc>,
+    pub id: Uuid,
+}
+
+impl Cursor {
+    pub fn encode(&self) -> String {
+        // Microseconds, the precision of Postgres timestamps
+        URL_SAFE_NO_PAD.encode(format!("{}:{}", self.timestamp.timestamp_micros(), self.id))
+    }
+
+    pub fn decode(s: &str) -> Result<Cursor, PaginationError> {
+        let invalid = || PaginationError::InvalidCursor(s.to_string());
+        let decoded = URL_SAFE_NO_PAD.decode(s).map_err(|_| invalid())?;
+        let decoded = String::from_utf8(decoded).map_err(|_| invalid())?;
+        let (timestamp, id) = decoded.split_once(':').ok_or_else(invalid)?;
+        let timestamp = timestamp
+            .parse()
+            .ok()
+            .and_then(DateTime::from_timestamp_micros)
+            .ok_or_else(invalid)?;
+        let id = id.parse().map_err(|_| invalid())?;
+        Ok(Cursor { timestamp, id })
+    }
+}
+
+/// Rows listed with keyset pagination
+pub trait Keyset {
+    fn cursor(&self) -> Cursor;
+}
+
+/// Validated pagination parameters of a list request
+#[derive(Debug)]
+pub struct PageRequest {
+    pub after: Option<Cursor>,
+    pub page_size: i64,
+}
+
+impl PageRequest {
+    pub fn parse(
+        cursor: Option<&str>,
+        page_size: Option<i64>,
+    ) -> Result<Self, Vec<PaginationError>> {
+        let after = cursor.map(Cursor::decode).transpose();
+        let page_size = page_size.unwrap_or(DEFAULT_PAGE_SIZE);
+        let page_size = if (1..=MAX_PAGE_SIZE).contains(&page_size) {
+            Ok(page_size)
+        } else {
+            Err(PaginationError::InvalidPageSize)
+        };
+        match (after, page_size) {
+            (Ok(after), Ok(page_size)) => Ok(Self { after, page_size }),
+            (after, page_size) => Err([after.err(), page_size.err()]
+                .into_iter()
+                .flatten()
+                .collect()),
+        }
+    }
+
+    /// Timestamp to bind to the keyset predicate, `None` for the first page
+    pub fn after_timestamp(&self) -> Option<DateTime<Utc>> {
+        self.after.map(|cursor| cursor.timestamp)
+    }
+
+    pub fn after_id(&self) -> Option<Uuid> {
+        self.after.map(|cursor| cursor.id)
+    }
+
+    /// Rows to fetch: one more than the page size, to tell whether there is a next page
+    pub fn limit(&self) -> i64 {
+        self.page_size + 1
+    }
+}
+
+/// One page of rows, with the cursor of the next page unless it is the last one
+#[derive(Debug)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub next_cursor: Option<String>,
+}
+
+impl<T: Keyset> Page<T> {
+    /// Build the page out of rows fetched with the [`PageRequest::limit`] of `request`
+    pub fn from_rows(mut rows: Vec<T>, request: &PageRequest) -> Self {
+        let page_size = usize::try_from(request.page_size).unwrap_or(usize::MAX);
+        let next_cursor = if rows.len() > page_size {
+            rows.truncate(page_size);
+            rows.last().map(|row| row.cursor().encode())
+        } else {
+            None
+        };
+        Self {
+            items: rows,
+            next_cursor,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+    use quickcheck::{Arbitrary, Gen};
+
+    #[derive(Debug, Clone)]
+    struct CursorFixture(Cursor);
+
+    impl Arbitrary for CursorFixture {
+        fn arbitrary(g: &mut Gen) -> Self {
+            // From the earliest Postgres timestamp, 4713-11-24 BC, to the latest one chrono
+            // supports, which comes before the latest Postgres one
+            let earliest = NaiveDate::from_ymd_opt(-4712, 11, 24)
+                .unwrap()
+                .and_hms_opt(0, 0, 0)
+                .unwrap()
+                .and_utc()
+                .timestamp_micros();
+            let latest = DateTime::<Utc>::MAX_UTC.timestamp_micros();
+            let span = u64::try_from(latest - earliest).unwrap();
+            let micros = earliest + i64::try_from(u64::arbitrary(g) % span).unwrap();
+            Self(Cursor {
+                timestamp: DateTime::from_timestamp_micros(micros).unwrap(),
+                id: Uuid::from_u128(u128::arbitrary(g)),
+            })
+        }
+    }
+
+    #[quickcheck_macros::quickcheck]
+    fn cursors_decode_to_what_was_encoded(cursor: CursorFixture) -> bool {
+        Cursor::decode(&cursor.0.encode()) == Ok(cursor.0)
+    }
+
+    #[test]
+    fn cursors_can_be_used_in_a_query_string() {
+        let cursor = Cursor {
+            timestamp: Utc::now(),
+            id: Uuid::new_v4(),
+        };
+        assert!(cursor
+            .encode()
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_".contains(c)));
+    }
+
+    #[test]
+    fn invalid_cursors_are_rejected() {
+        let not_a_uuid = URL_SAFE_NO_PAD.encode("1700000000000000:not-a-uuid");
+        let not_a_timestamp = URL_SAFE_NO_PAD.encode(format!("yesterday:{}", Uuid::new_v4()));
+        for cursor in ["", "!!!", "bm8tY29sb24", &not_a_uuid, &not_a_timestamp] {
+            assert!(Cursor::decode(cursor).is_err(), "{cursor} was accepted");
+        }
+    }
+
+    #[test]
+    fn page_requests_are_validated() {
+        assert_eq!(PageRequest::parse(None, None).unwrap().page_size, 50);
+        let errors = PageRequest::parse(Some("!!!"), Some(0)).unwrap_err();
+        let parameters: Vec<_> = errors.iter().map(PaginationError::parameter).collect();
+        assert_eq!(parameters, vec!["cursor", "page_size"]);
+        assert!(PageRequest::parse(None, Some(MAX_PAGE_SIZE + 1)).is_err());
+    }
+
+    struct Row(Cursor);
+
+    impl Keyset for Row {
+        fn cursor(&self) -> Cursor {
+            self.0
+        }
+    }
+
+    fn rows(n: usize) -> Vec<Row> {
+        (0..n)
+            .map(|_| {
+                Row(Cursor {
+                    timestamp: Utc::now(),
+                    id: Uuid::new_v4(),
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn the_extra_row_is_dropped_and_gives_the_next_cursor() {
+        // Arrange
+        let request = PageRequest::parse(None, Some(2)).unwrap();
+        let rows = rows(3);
+        let last_in_page = rows[1].0;
+        // Act
+        let page = Page::from_rows(rows, &request);
+        // Assert
+        assert_eq!(page.items.len(), 2);
+        assert_eq!(page.next_cursor, Some(last_in_page.encode()));
+    }
+
+    #[test]
+    fn the_last_page_has_no_next_cursor() {
+        let request = PageRequest::parse(None, Some(2)).unwrap();
+        for n in 0..=2 {
+            let page = Page::from_rows(rows(n), &request);
+            assert_eq!(page.items.len(), n);
+            assert_eq!(page.next_cursor, None);
+        }
+    }
+}
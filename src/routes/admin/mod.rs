@@ -0,0 +1,10 @@
+mod dashboard;
+mod logout;
+mod newsletters;
+mod password;
+mod subscribers;
+pub use dashboard::*;
+pub use logout::*;
+pub use newsletters::*;
+pub use password::*;
+pub use subscribers::*;
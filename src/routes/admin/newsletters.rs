@@ -0,0 +1,134 @@
+use crate::utils::e500;
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Number of failed deliveries returned with the status of an issue
+const FAILURE_SAMPLE_SIZE: i64 = 10;
+
+#[derive(serde::Serialize)]
+pub struct IssueStatus {
+    pub newsletter_issue_id: Uuid,
+    pub title: String,
+    pub published_at: DateTime<Utc>,
+    pub counts: DeliveryCounts,
+    /// A sample of the failed deliveries, most recent first
+    pub failures: Vec<DeliveryFailure>,
+}
+
+/// Recipients of an issue per delivery status
+#[derive(serde::Serialize, Default)]
+pub struct DeliveryCounts {
+    pub queued: i64,
+    pub sent: i64,
+    pub failed: i64,
+    /// Not sent because the recipient unsubscribed or was deleted in the meantime
+    pub cancelled: i64,
+}
+
+#[derive(serde::Serialize)]
+pub struct DeliveryFailure {
+    pub subscriber_email: String,
+    pub error: String,
+}
+
+#[tracing::instrument(name = "Get the delivery status of a newsletter issue", skip(pool))]
+pub async fn newsletter_issue_status(
+    newsletter_issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let newsletter_issue_id = newsletter_issue_id.into_inner();
+    let Some(issue) = get_issue(&pool, newsletter_issue_id).await.map_err(e500)? else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    let counts = get_delivery_counts(&pool, newsletter_issue_id)
+        .await
+        .map_err(e500)?;
+    let failures = get_delivery_failures(&pool, newsletter_issue_id)
+        .await
+        .map_err(e500)?;
+    Ok(HttpResponse::Ok().json(IssueStatus {
+        newsletter_issue_id,
+        title: issue.title,
+        published_at: issue.published_at,
+        counts,
+        failures,
+    }))
+}
+
+struct Issue {
+    title: String,
+    published_at: DateTime<Utc>,
+}
+
+async fn get_issue(
+    pool: &PgPool,
+    newsletter_issue_id: Uuid,
+) -> Result<Option<Issue>, anyhow::Error> {
+    sqlx::query_as!(
+        Issue,
+        r#"
+SELECT title, published_at
+FROM newsletter_issues
+WHERE newsletter_issue_id = $1
+"#,
+        newsletter_issue_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve a newsletter issue")
+}
+
+async fn get_delivery_counts(
+    pool: &PgPool,
+    newsletter_issue_id: Uuid,
+) -> Result<DeliveryCounts, anyhow::Error> {
+    let rows = sqlx::query!(
+        r#"
+SELECT q.status, count(*) AS "count!"
+FROM issue_deliveries d
+JOIN email_delivery_queue q ON q.id = d.email_id
+WHERE d.newsletter_issue_id = $1
+GROUP BY q.status
+"#,
+        newsletter_issue_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to count the deliveries of a newsletter issue")?;
+    let mut counts = DeliveryCounts::default();
+    for row in rows {
+        match row.status.as_str() {
+            "pending" => counts.queued = row.count,
+            "sent" => counts.sent = row.count,
+            "failed" => counts.failed = row.count,
+            "cancelled" => counts.cancelled = row.count,
+            status => tracing::warn!(status, "Unknown email delivery status"),
+        }
+    }
+    Ok(counts)
+}
+
+async fn get_delivery_failures(
+    pool: &PgPool,
+    newsletter_issue_id: Uuid,
+) -> Result<Vec<DeliveryFailure>, anyhow::Error> {
+    sqlx::query_as!(
+        DeliveryFailure,
+        r#"
+SELECT d.subscriber_email, coalesce(q.last_error, '') AS "error!"
+FROM issue_deliveries d
+JOIN email_delivery_queue q ON q.id = d.email_id
+WHERE d.newsletter_issue_id = $1 AND q.status = 'failed'
+ORDER BY q.completed_at DESC
+LIMIT $2
+"#,
+        newsletter_issue_id,
+        FAILURE_SAMPLE_SIZE
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the failed deliveries of a newsletter issue")
+}
@@ -0,0 +1,326 @@
+use crate::domain::{SubscriberEmail, SubscriberName, SubscriptionStatus};
+use crate::email_delivery_worker::cancel_pending_emails;
+use crate::pagination::{Cursor, Keyset, Page, PageRequest, PaginationError};
+use crate::routes::{error_chain_fmt, FieldError};
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse, ResponseError};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Serialize)]
+pub struct Subscriber {
+    pub id: Uuid,
+    pub email: String,
+    pub name: String,
+    pub status: String,
+    pub subscribed_at: DateTime<Utc>,
+}
+
+impl Keyset for Subscriber {
+    fn cursor(&self) -> Cursor {
+        Cursor {
+            timestamp: self.subscribed_at,
+            id: self.id,
+        }
+    }
+}
+
+#[derive(thiserror::Error)]
+pub enum SubscriberAdminError {
+    #[error("Invalid subscriber data")]
+    ValidationError(Vec<FieldError>),
+    #[error("The email address is already used by another subscriber")]
+    EmailTaken,
+    #[error("Subscriber not found")]
+    NotFound,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for SubscriberAdminError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for SubscriberAdminError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            SubscriberAdminError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            SubscriberAdminError::EmailTaken => StatusCode::CONFLICT,
+            SubscriberAdminError::NotFound => StatusCode::NOT_FOUND,
+            SubscriberAdminError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            SubscriberAdminError::ValidationError(errors) => {
+                HttpResponse::BadRequest().json(serde_json::json!({ "errors": errors }))
+            }
+            SubscriberAdminError::EmailTaken => {
+                let errors = [FieldError::new("email", self.to_string())];
+                HttpResponse::Conflict().json(serde_json::json!({ "errors": errors }))
+            }
+            SubscriberAdminError::NotFound => HttpResponse::NotFound().finish(),
+            SubscriberAdminError::UnexpectedError(_) => {
+                tracing::error!(error.cause_chain = ?self, "Failed to manage subscribers");
+                HttpResponse::new(self.status_code())
+            }
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct ListParameters {
+    status: Option<String>,
+    /// Case-insensitive substring of the email address
+    email: Option<String>,
+    /// `next_cursor` of the previous page
+    cursor: Option<String>,
+    page_size: Option<i64>,
+}
+
+#[derive(serde::Serialize)]
+pub struct SubscriberPage {
+    pub subscribers: Vec<Subscriber>,
+    /// `null` on the last page
+    pub next_cursor: Option<String>,
+}
+
+/// Subscribers matching the filters, oldest first
+#[tracing::instrument(name = "List subscribers", skip(parameters, pool))]
+pub async fn list_subscribers(
+    parameters: web::Query<ListParameters>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, SubscriberAdminError> {
+    let status = parameters
+        .status
+        .as_deref()
+        .map(SubscriptionStatus::parse)
+        .transpose()
+        .map_err(|e| FieldError::new("status", e));
+    let page_request = PageRequest::parse(parameters.cursor.as_deref(), parameters.page_size);
+    let (status, page_request) = match (status, page_request) {
+        (Ok(status), Ok(page_request)) => (status, page_request),
+        (status, page_request) => {
+            let errors = status
+                .err()
+                .into_iter()
+                .chain(
+                    page_request
+                        .err()
+                        .into_iter()
+                        .flatten()
+                        .map(FieldError::from),
+                )
+                .collect();
+            return Err(SubscriberAdminError::ValidationError(errors));
+        }
+    };
+    let email_pattern = parameters
+        .email
+        .as_deref()
+        .map(|email| format!("%{}%", escape_like(email)));
+    let rows = sqlx::query_as!(
+        Subscriber,
+        r#"
+SELECT id, email, name, status, subscribed_at
+FROM subscriptions
+WHERE ($1::text IS NULL OR status = $1)
+  AND ($2::text IS NULL OR email ILIKE $2)
+  AND ($3::timestamptz IS NULL OR (subscribed_at, id) > ($3, $4))
+ORDER BY subscribed_at, id
+LIMIT $5
+"#,
+        status.as_ref().map(AsRef::as_ref),
+        email_pattern,
+        page_request.after_timestamp(),
+        page_request.after_id(),
+        page_request.limit()
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .context("Failed to list subscribers")?;
+    let page = Page::from_rows(rows, &page_request);
+    Ok(HttpResponse::Ok().json(SubscriberPage {
+        subscribers: page.items,
+        next_cursor: page.next_cursor,
+    }))
+}
+
+impl From<PaginationError> for FieldError {
+    fn from(error: PaginationError) -> Self {
+        FieldError::new(error.parameter(), error.to_string())
+    }
+}
+
+/// Match `%` and `_` literally in a `LIKE` pattern
+fn escape_like(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+#[tracing::instrument(name = "Get a subscriber", skip(pool))]
+pub async fn get_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, SubscriberAdminError> {
+    let subscriber = sqlx::query_as!(
+        Subscriber,
+        r#"
+SELECT id, email, name, status, subscribed_at
+FROM subscriptions
+WHERE id = $1
+"#,
+        subscriber_id.into_inner()
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to retrieve a subscriber")?
+    .ok_or(SubscriberAdminError::NotFound)?;
+    Ok(HttpResponse::Ok().json(subscriber))
+}
+
+/// Fields to change, the others are left as they are
+#[derive(serde::Deserialize)]
+pub struct SubscriberUpdate {
+    name: Option<String>,
+    email: Option<String>,
+    status: Option<String>,
+}
+
+/// Unsubscribing someone cancels the emails queued for them
+#[tracing::instrument(name = "Update a subscriber", skip(body, pool))]
+pub async fn update_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    body: web::Json<SubscriberUpdate>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, SubscriberAdminError> {
+    let name = body.name.as_deref().map(SubscriberName::parse).transpose();
+    let email = body
+        .email
+        .as_deref()
+        .map(SubscriberEmail::parse)
+        .transpose();
+    let status = body
+        .status
+        .as_deref()
+        .map(parse_status_transition)
+        .transpose();
+    let (name, email, status) = match (name, email, status) {
+        (Ok(name), Ok(email), Ok(status)) => (name, email, status),
+        (name, email, status) => {
+            let errors = [
+                name.err().map(|e| FieldError::new("name", e)),
+                email.err().map(|e| FieldError::new("email", e)),
+                status.err().map(|e| FieldError::new("status", e)),
+            ];
+            return Err(SubscriberAdminError::ValidationError(
+                errors.into_iter().flatten().collect(),
+            ));
+        }
+    };
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let subscriber = sqlx::query_as!(
+        Subscriber,
+        r#"
+UPDATE subscriptions
+SET name = coalesce($2, name),
+    email = coalesce($3, email),
+    status = coalesce($4, status)
+WHERE id = $1
+RETURNING id, email, name, status, subscribed_at
+"#,
+        subscriber_id.into_inner(),
+        name.as_ref().map(AsRef::as_ref),
+        email.as_ref().map(AsRef::as_ref),
+        status.as_ref().map(AsRef::as_ref)
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::Database(e) if e.is_unique_violation() => SubscriberAdminError::EmailTaken,
+        e => anyhow::Error::new(e)
+            .context("Failed to update a subscriber")
+            .into(),
+    })?
+    .ok_or(SubscriberAdminError::NotFound)?;
+    if status == Some(SubscriptionStatus::Unsubscribed) {
+        cancel_pending_emails(
+            &mut transaction,
+            &subscriber.email,
+            "Recipient unsubscribed",
+        )
+        .await
+        .context("Failed to cancel the emails queued for an unsubscribed subscriber")?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to update a subscriber")?;
+    Ok(HttpResponse::Ok().json(subscriber))
+}
+
+/// Subscribers only become pending again by subscribing, which sends them a confirmation email
+fn parse_status_transition(s: &str) -> Result<SubscriptionStatus, String> {
+    match SubscriptionStatus::parse(s)? {
+        SubscriptionStatus::PendingConfirmation => {
+            Err("Subscribers cannot be set back to pending_confirmation".into())
+        }
+        status => Ok(status),
+    }
+}
+
+/// Delete a subscriber along with their confirmation tokens, cancelling the emails queued for them
+#[tracing::instrument(name = "Delete a subscriber", skip(pool))]
+pub async fn delete_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, SubscriberAdminError> {
+    let subscriber_id = subscriber_id.into_inner();
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    sqlx::query!(
+        r#"DELETE FROM subscription_tokens WHERE subscriber_id = $1"#,
+        subscriber_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to delete the confirmation tokens of a subscriber")?;
+    let deleted = sqlx::query!(
+        r#"DELETE FROM subscriptions WHERE id = $1 RETURNING email"#,
+        subscriber_id
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to delete a subscriber")?
+    .ok_or(SubscriberAdminError::NotFound)?;
+    cancel_pending_emails(&mut transaction, &deleted.email, "Recipient deleted")
+        .await
+        .context("Failed to cancel the emails queued for a deleted subscriber")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to delete a subscriber")?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::escape_like;
+
+    #[test]
+    fn like_wildcards_are_escaped() {
+        assert_eq!(escape_like("le_guin%"), "le\\_guin\\%");
+        assert_eq!(escape_like("a\\b"), "a\\\\b");
+        assert_eq!(escape_like("ursula"), "ursula");
+    }
+}
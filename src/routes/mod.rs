@@ -1,4 +1,14 @@
+mod admin;
 mod health_check;
+mod login;
+mod newsletters;
 mod subscriptions;
+mod subscriptions_confirm;
+mod subscriptions_unsubscribe;
+pub use admin::*;
 pub use health_check::*;
+pub use login::*;
+pub use newsletters::*;
 pub use subscriptions::*;
+pub use subscriptions_confirm::*;
+pub use subscriptions_unsubscribe::*;
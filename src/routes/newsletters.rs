@@ -0,0 +1,191 @@
+use crate::{
+    domain::SubscriberEmail,
+    email_delivery_worker::enqueue_email,
+    routes::{error_chain_fmt, unsubscribe_link},
+    startup::ApplicationBaseUrl,
+};
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse, ResponseError};
+use anyhow::Context;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct BodyData {
+    title: String,
+    content: Content,
+}
+
+#[derive(serde::Deserialize)]
+pub struct Content {
+    html: String,
+    text: String,
+}
+
+#[derive(thiserror::Error)]
+pub enum PublishError {
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for PublishError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for PublishError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            PublishError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+    fn error_response(&self) -> HttpResponse {
+        tracing::error!(error.cause_chain = ?self, "Failed to publish a newsletter issue");
+        HttpResponse::new(self.status_code())
+    }
+}
+
+/// Queue the issue for every confirmed subscriber, the delivery worker sends it
+#[tracing::instrument(
+    name = "Publish a newsletter issue",
+    skip(body, pool, base_url),
+    fields(title = %body.title)
+)]
+pub async fn publish_newsletter(
+    body: web::Json<BodyData>,
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, PublishError> {
+    let subscribers = get_confirmed_subscribers(&pool).await?;
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let newsletter_issue_id = insert_newsletter_issue(&mut transaction, &body)
+        .await
+        .context("Failed to store newsletter issue details")?;
+    for subscriber in subscribers {
+        match subscriber {
+            Ok(subscriber) => {
+                let email_id = enqueue_email(
+                    &mut transaction,
+                    &subscriber.email,
+                    &body.title,
+                    &body.content.html,
+                    &body.content.text,
+                    &unsubscribe_link(&base_url.0, &subscriber.unsubscribe_token),
+                )
+                .await
+                .with_context(|| {
+                    format!(
+                        "Failed to queue the newsletter issue to {}",
+                        subscriber.email.as_ref()
+                    )
+                })?;
+                record_issue_delivery(
+                    &mut transaction,
+                    newsletter_issue_id,
+                    &subscriber.email,
+                    email_id,
+                )
+                .await
+                .context("Failed to record the delivery of a newsletter issue")?;
+            }
+            Err(error) => {
+                tracing::warn!(
+                    error.cause_chain = ?error,
+                    "Skipping a confirmed subscriber. Their stored contact details are invalid",
+                );
+            }
+        }
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to queue a newsletter issue")?;
+    Ok(HttpResponse::Ok().json(PublishResponse {
+        newsletter_issue_id,
+    }))
+}
+
+/// Body of a successful publication, the id gives access to the delivery status of the issue
+#[derive(serde::Serialize)]
+pub struct PublishResponse {
+    pub newsletter_issue_id: Uuid,
+}
+
+#[tracing::instrument(skip_all)]
+async fn insert_newsletter_issue(
+    transaction: &mut Transaction<'_, Postgres>,
+    body: &BodyData,
+) -> Result<Uuid, sqlx::Error> {
+    let newsletter_issue_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+INSERT INTO newsletter_issues (
+    newsletter_issue_id, title, text_content, html_content, published_at
+)
+VALUES ($1, $2, $3, $4, now())
+"#,
+        newsletter_issue_id,
+        body.title,
+        body.content.text,
+        body.content.html
+    )
+    .execute(&mut **transaction)
+    .await?;
+    Ok(newsletter_issue_id)
+}
+
+#[tracing::instrument(skip_all)]
+async fn record_issue_delivery(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+    subscriber_email: &SubscriberEmail,
+    email_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+INSERT INTO issue_deliveries (newsletter_issue_id, subscriber_email, email_id)
+VALUES ($1, $2, $3)
+"#,
+        newsletter_issue_id,
+        subscriber_email.as_ref(),
+        email_id
+    )
+    .execute(&mut **transaction)
+    .await?;
+    Ok(())
+}
+
+struct ConfirmedSubscriber {
+    email: SubscriberEmail,
+    unsubscribe_token: String,
+}
+
+#[tracing::instrument(name = "Get confirmed subscribers", skip(pool))]
+async fn get_confirmed_subscribers(
+    pool: &PgPool,
+) -> Result<Vec<Result<ConfirmedSubscriber, anyhow::Error>>, anyhow::Error> {
+    let confirmed_subscribers = sqlx::query!(
+        r#"
+SELECT email, unsubscribe_token
+FROM subscriptions
+WHERE status = 'confirmed'
+"#,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to fetch confirmed subscribers")?
+    .into_iter()
+    .map(|r| match SubscriberEmail::parse(&r.email) {
+        Ok(email) => Ok(ConfirmedSubscriber {
+            email,
+            unsubscribe_token: r.unsubscribe_token,
+        }),
+        Err(error) => Err(anyhow::anyhow!(error)),
+    })
+    .collect();
+    Ok(confirmed_subscribers)
+}
@@ -1,10 +1,18 @@
 use crate::{
-    domain::{NewSubscriber, SubscriberEmail, SubscriberName},
-    email_client::EmailClient,
+    configuration::{ConfirmationTokenMode, ConfirmationTokenSettings},
+    domain::{NewSubscriber, SignedToken, SubscriberEmail, SubscriberName, SubscriptionToken},
+    email_delivery_worker::enqueue_email,
+    routes::unsubscribe_link,
+    startup::{ApplicationBaseUrl, SubscriptionTokenTtl},
 };
-use actix_web::{web, HttpResponse};
-use chrono::Utc;
-use sqlx::PgPool;
+use actix_web::dev::Payload;
+use actix_web::http::{header, StatusCode};
+use actix_web::{web, FromRequest, HttpMessage, HttpRequest, HttpResponse, ResponseError};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use std::future::Future;
+use std::pin::Pin;
 use uuid::Uuid;
 
 #[derive(serde::Deserialize)]
@@ -13,72 +21,295 @@ pub struct FormData {
     name: String,
 }
 
+/// Subscription data sent either as an urlencoded form or as JSON, depending on the content type
+pub struct SubscriptionData(pub FormData);
+
+impl FromRequest for SubscriptionData {
+    type Error = actix_web::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(request: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        if request.content_type() == "application/json" {
+            let json = web::Json::<FormData>::from_request(request, payload);
+            Box::pin(async move { Ok(Self(json.await?.into_inner())) })
+        } else {
+            let form = web::Form::<FormData>::from_request(request, payload);
+            Box::pin(async move { Ok(Self(form.await?.into_inner())) })
+        }
+    }
+}
+
 impl TryFrom<FormData> for NewSubscriber {
-    type Error = String;
+    type Error = Vec<FieldError>;
     fn try_from(form: FormData) -> Result<Self, Self::Error> {
-        let name = SubscriberName::parse(&form.name)?;
-        let email = SubscriberEmail::parse(&form.email)?;
-        Ok(NewSubscriber { name, email })
+        let name = SubscriberName::parse(&form.name).map_err(|e| FieldError::new("name", e));
+        let email = SubscriberEmail::parse(&form.email).map_err(|e| FieldError::new("email", e));
+        match (name, email) {
+            (Ok(name), Ok(email)) => Ok(NewSubscriber { name, email }),
+            (name, email) => Err([name.err(), email.err()].into_iter().flatten().collect()),
+        }
+    }
+}
+
+/// Input field rejected during validation, and why
+#[derive(serde::Serialize, Debug)]
+pub struct FieldError {
+    pub field: &'static str,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: &'static str, message: String) -> Self {
+        Self { field, message }
     }
 }
 
+#[derive(thiserror::Error)]
+pub enum SubscribeError {
+    #[error("Invalid subscription data")]
+    ValidationError(Vec<FieldError>),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for SubscribeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for SubscribeError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            SubscribeError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            SubscribeError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            SubscribeError::ValidationError(errors) => {
+                HttpResponse::BadRequest().json(serde_json::json!({ "errors": errors }))
+            }
+            SubscribeError::UnexpectedError(_) => {
+                tracing::error!(error.cause_chain = ?self, "Failed to add a new subscriber");
+                HttpResponse::new(self.status_code())
+            }
+        }
+    }
+}
+
+/// Format an error followed by every error in its `source` chain
+pub fn error_chain_fmt(
+    e: &impl std::error::Error,
+    f: &mut std::fmt::Formatter<'_>,
+) -> std::fmt::Result {
+    writeln!(f, "{e}\n")?;
+    let mut current = e.source();
+    while let Some(cause) = current {
+        writeln!(f, "Caused by:\n\t{cause}")?;
+        current = cause.source();
+    }
+    Ok(())
+}
+
+/// Body of a successful subscription, for clients accepting JSON. It is the same whether the email
+/// was new, pending or already confirmed, so that callers cannot probe who subscribed.
+#[derive(serde::Serialize)]
+pub struct SubscriptionResponse {
+    pub status: &'static str,
+}
+
 #[tracing::instrument(
     name = "Adding a new subscriber",
-    skip(form, pool),
-    fields(
-subscriber_email = %form.email,
-subscriber_name= %form.name
-    )
+    skip(request, body, pool, base_url, token_ttl, confirmation_tokens),
+    fields(subscriber_email = tracing::field::Empty, subscriber_name = tracing::field::Empty)
 )]
 pub async fn subscribe(
-    form: web::Form<FormData>,
+    request: HttpRequest,
+    body: SubscriptionData,
     pool: web::Data<PgPool>,
-    email_client: web::Data<EmailClient>,
-) -> HttpResponse {
-    if let Ok(new_subscriber) = form.0.try_into() {
-        match insert_subscriber(&pool, &new_subscriber).await {
-            Ok(_) => {
-                let confirmation_link = "https://my-api.com/subscriptions/confirm";
-                let body = format!(
-                    "Click <a href=\"{confirmation_link}\">here</a> to confirm your subscription."
-                );
-                match email_client
-                    .send_email(new_subscriber.email, "Welcome", &body)
-                    .await
-                {
-                    Ok(_) => HttpResponse::Ok().finish(),
-                    Err(e) => {
-                        tracing::error!("{e:?}");
-                        HttpResponse::InternalServerError().finish()
-                    }
-                }
-            }
-            Err(_) => HttpResponse::InternalServerError().finish(),
+    base_url: web::Data<ApplicationBaseUrl>,
+    token_ttl: web::Data<SubscriptionTokenTtl>,
+    confirmation_tokens: web::Data<ConfirmationTokenSettings>,
+) -> Result<HttpResponse, SubscribeError> {
+    let form = body.0;
+    tracing::Span::current()
+        .record("subscriber_email", tracing::field::display(&form.email))
+        .record("subscriber_name", tracing::field::display(&form.name));
+    let new_subscriber: NewSubscriber = form.try_into().map_err(SubscribeError::ValidationError)?;
+    // The subscriber, its token and the confirmation email are stored all together or not at all:
+    // returning before `commit` drops the transaction, which rolls it back
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let subscriber = upsert_subscriber(&mut transaction, &new_subscriber)
+        .await
+        .context("Failed to insert new subscriber in the database")?;
+    if subscriber.status == "confirmed" {
+        // Nothing to confirm, and the upsert left the subscriber as it was
+        return Ok(subscribed(&request));
+    }
+    // Pending subscribers get a fresh token, links sent earlier stay valid
+    let subscription_token = match confirmation_tokens.mode {
+        ConfirmationTokenMode::Table => {
+            let subscription_token = SubscriptionToken::generate();
+            let expires_at = Utc::now() + token_ttl.0;
+            store_token(
+                &mut transaction,
+                subscriber.id,
+                &subscription_token,
+                expires_at,
+            )
+            .await
+            .context("Failed to store the confirmation token for a new subscriber")?;
+            subscription_token.as_ref().to_string()
+        }
+        ConfirmationTokenMode::Signed => {
+            SignedToken::new(subscriber.id, SignedToken::SUBSCRIPTION_CONFIRMATION)
+                .sign(confirmation_tokens.signing_key())
         }
-    } else {
-        HttpResponse::BadRequest().finish()
+    };
+    let confirmation_email = ConfirmationEmail::new(&base_url.0, &subscription_token);
+    enqueue_email(
+        &mut transaction,
+        &new_subscriber.email,
+        CONFIRMATION_EMAIL_SUBJECT,
+        &confirmation_email.html_content,
+        &confirmation_email.text_content,
+        &unsubscribe_link(&base_url.0, &subscriber.unsubscribe_token),
+    )
+    .await
+    .context("Failed to queue the confirmation email")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to store a new subscriber")?;
+    Ok(subscribed(&request))
+}
+
+/// Answer with a JSON body if the client accepts it, with an empty body otherwise
+fn subscribed(request: &HttpRequest) -> HttpResponse {
+    if !accepts_json(request) {
+        return HttpResponse::Ok().finish();
     }
+    HttpResponse::Ok().json(SubscriptionResponse {
+        status: "pending_confirmation",
+    })
+}
+
+fn accepts_json(request: &HttpRequest) -> bool {
+    request
+        .headers()
+        .get(header::ACCEPT)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|accept| accept.contains("application/json"))
+}
+
+const CONFIRMATION_EMAIL_SUBJECT: &str = "Welcome";
+
+/// Content of the email asking a new subscriber to confirm their address
+pub struct ConfirmationEmail {
+    pub html_content: String,
+    pub text_content: String,
 }
 
+impl ConfirmationEmail {
+    pub fn new(base_url: &str, subscription_token: &str) -> Self {
+        let confirmation_link =
+            format!("{base_url}/subscriptions/confirm?subscription_token={subscription_token}");
+        let html_content = format!(
+            "Welcome to our newsletter!<br />\
+            Click <a href=\"{confirmation_link}\">here</a> to confirm your subscription."
+        );
+        let text_content = format!(
+            "Welcome to our newsletter!\nVisit {confirmation_link} to confirm your subscription."
+        );
+        Self {
+            html_content,
+            text_content,
+        }
+    }
+}
+
+/// Subscriber row as stored after [`upsert_subscriber`]
+pub struct StoredSubscriber {
+    pub id: Uuid,
+    pub status: String,
+    pub unsubscribe_token: String,
+}
+
+/// Insert a new pending subscriber, or update the one already using this email.
+///
+/// Subscribers who unsubscribed earlier are pending confirmation again. Unless confirmed, their
+/// name is updated and their confirmation window restarts: anyone can post any email address, so
+/// confirmed subscribers are left untouched. The subscription date never changes, as it orders
+/// the paginated listing of subscribers.
 #[tracing::instrument(
     name = "Saving new subscriber details in the database",
-    skip(pool, subscriber)
+    skip(transaction, subscriber)
 )]
-pub async fn insert_subscriber(
-    pool: &PgPool,
+pub async fn upsert_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
     subscriber: &NewSubscriber,
-) -> Result<(), sqlx::Error> {
-    sqlx::query!(
+) -> Result<StoredSubscriber, sqlx::Error> {
+    let unsubscribe_token = SubscriptionToken::generate();
+    sqlx::query_as!(
+        StoredSubscriber,
         r#"
-INSERT INTO subscriptions (id, email, name, subscribed_at, status)
-VALUES ($1, $2, $3, $4, 'confirmed')
+INSERT INTO subscriptions (
+    id, email, name, subscribed_at, confirmation_requested_at, status, unsubscribe_token
+)
+VALUES ($1, $2, $3, $4, $4, 'pending_confirmation', $5)
+ON CONFLICT (email) DO UPDATE SET
+    name = CASE
+        WHEN subscriptions.status = 'confirmed' THEN subscriptions.name
+        ELSE EXCLUDED.name
+    END,
+    confirmation_requested_at = CASE
+        WHEN subscriptions.status = 'confirmed' THEN subscriptions.confirmation_requested_at
+        ELSE EXCLUDED.confirmation_requested_at
+    END,
+    status = CASE
+        WHEN subscriptions.status = 'unsubscribed' THEN 'pending_confirmation'
+        ELSE subscriptions.status
+    END
+RETURNING id, status, unsubscribe_token
 "#,
         Uuid::new_v4(),
         subscriber.email.as_ref(),
         subscriber.name.as_ref(),
-        Utc::now()
+        Utc::now(),
+        unsubscribe_token.as_ref()
     )
-    .execute(pool)
+    .fetch_one(&mut **transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {e:?}");
+        e
+    })
+}
+
+#[tracing::instrument(
+    name = "Store subscription token in the database",
+    skip(transaction, subscription_token)
+)]
+pub async fn store_token(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    subscription_token: &SubscriptionToken,
+    expires_at: DateTime<Utc>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+INSERT INTO subscription_tokens (subscription_token_hash, subscriber_id, expires_at)
+VALUES ($1, $2, $3)
+"#,
+        subscription_token.hash(),
+        subscriber_id,
+        expires_at
+    )
+    .execute(&mut **transaction)
     .await
     .map_err(|e| {
         tracing::error!("Failed to execute query: {e:?}");
@@ -86,3 +317,19 @@ VALUES ($1, $2, $3, $4, 'confirmed')
     })?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn confirmation_email_links_to_the_confirm_endpoint_with_the_token() {
+        // Arrange
+        let link = "http://127.0.0.1/subscriptions/confirm?subscription_token=mytoken";
+        // Act
+        let email = ConfirmationEmail::new("http://127.0.0.1", "mytoken");
+        // Assert
+        assert!(email.html_content.contains(&format!("href=\"{link}\"")));
+        assert!(email.text_content.contains(link));
+    }
+}
@@ -0,0 +1,114 @@
+use crate::configuration::{ConfirmationTokenMode, ConfirmationTokenSettings};
+use crate::domain::{SignedToken, SignedTokenError, SubscriptionToken};
+use crate::startup::SubscriptionTokenTtl;
+use actix_web::{web, HttpResponse};
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct Parameters {
+    subscription_token: String,
+}
+
+#[tracing::instrument(
+    name = "Confirm a pending subscriber",
+    skip(parameters, pool, token_ttl, confirmation_tokens)
+)]
+pub async fn confirm(
+    parameters: web::Query<Parameters>,
+    pool: web::Data<PgPool>,
+    token_ttl: web::Data<SubscriptionTokenTtl>,
+    confirmation_tokens: web::Data<ConfirmationTokenSettings>,
+) -> HttpResponse {
+    let subscriber_id = match confirmation_tokens.mode {
+        ConfirmationTokenMode::Table => {
+            match get_subscriber_id_from_stored_token(&pool, &parameters.subscription_token).await {
+                Ok(subscriber_id) => subscriber_id,
+                Err(response) => return response,
+            }
+        }
+        ConfirmationTokenMode::Signed => match SignedToken::verify(
+            &parameters.subscription_token,
+            confirmation_tokens.signing_key(),
+            SignedToken::SUBSCRIPTION_CONFIRMATION,
+            token_ttl.0,
+        ) {
+            Ok(token) => token.subscriber_id,
+            Err(SignedTokenError::Expired) => return expired(),
+            Err(error) => {
+                tracing::warn!(%error, "Rejected a signed confirmation token");
+                return HttpResponse::Unauthorized().finish();
+            }
+        },
+    };
+    match confirm_subscriber(&pool, subscriber_id).await {
+        Ok(true) => HttpResponse::Ok().finish(),
+        // Deleted or unsubscribed since the link was sent
+        Ok(false) => HttpResponse::Unauthorized().finish(),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+fn expired() -> HttpResponse {
+    HttpResponse::Gone()
+        .body("This confirmation link has expired. Subscribe again to receive a new one.")
+}
+
+/// Look the token up in `subscription_tokens`, answering right away if it cannot be used
+async fn get_subscriber_id_from_stored_token(
+    pool: &PgPool,
+    subscription_token: &str,
+) -> Result<Uuid, HttpResponse> {
+    let Ok(subscription_token) = SubscriptionToken::parse(subscription_token) else {
+        return Err(HttpResponse::Unauthorized().finish());
+    };
+    match get_token(pool, &subscription_token).await {
+        Err(_) => Err(HttpResponse::InternalServerError().finish()),
+        Ok(None) => Err(HttpResponse::Unauthorized().finish()),
+        Ok(Some(token)) if token.expires_at <= Utc::now() => Err(expired()),
+        Ok(Some(token)) => Ok(token.subscriber_id),
+    }
+}
+
+/// Returns whether the subscriber was confirmed.
+///
+/// Links from emails sent before unsubscribing do not subscribe again.
+#[tracing::instrument(name = "Mark subscriber as confirmed", skip(pool, subscriber_id))]
+pub async fn confirm_subscriber(pool: &PgPool, subscriber_id: Uuid) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"UPDATE subscriptions SET status = 'confirmed' WHERE id = $1 AND status <> 'unsubscribed'"#,
+        subscriber_id,
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {e:?}");
+        e
+    })?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Stored confirmation token
+pub struct StoredToken {
+    pub subscriber_id: Uuid,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(name = "Get subscription token", skip(pool, subscription_token))]
+pub async fn get_token(
+    pool: &PgPool,
+    subscription_token: &SubscriptionToken,
+) -> Result<Option<StoredToken>, sqlx::Error> {
+    sqlx::query_as!(
+        StoredToken,
+        r#"SELECT subscriber_id, expires_at FROM subscription_tokens WHERE subscription_token_hash = $1"#,
+        subscription_token.hash(),
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {e:?}");
+        e
+    })
+}
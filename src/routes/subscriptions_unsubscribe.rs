@@ -0,0 +1,115 @@
+use crate::email_delivery_worker::cancel_pending_emails;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+
+#[derive(serde::Deserialize)]
+pub struct UnsubscribeParameters {
+    unsubscribe_token: String,
+}
+
+/// Link leading a subscriber to the unsubscribe confirmation page.
+///
+/// The same URL accepts the RFC 8058 one-click `POST` announced by `List-Unsubscribe-Post`.
+pub fn unsubscribe_link(base_url: &str, unsubscribe_token: &str) -> String {
+    format!("{base_url}/subscriptions/unsubscribe?unsubscribe_token={unsubscribe_token}")
+}
+
+/// Ask for a confirmation first, so that link scanners following a `GET` unsubscribe nobody
+#[tracing::instrument(name = "Show the unsubscribe page", skip(parameters, pool))]
+pub async fn unsubscribe_form(
+    parameters: web::Query<UnsubscribeParameters>,
+    pool: web::Data<PgPool>,
+) -> HttpResponse {
+    match subscription_exists(&pool, &parameters.unsubscribe_token).await {
+        Ok(true) => {}
+        Ok(false) => return HttpResponse::Unauthorized().finish(),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    }
+    let action = format!(
+        "/subscriptions/unsubscribe?unsubscribe_token={}",
+        htmlescape::encode_attribute(&parameters.unsubscribe_token)
+    );
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Unsubscribe</title>
+</head>
+<body>
+    <p>Do you want to stop receiving our newsletter?</p>
+    <form action="{action}" method="post">
+        <button type="submit">Unsubscribe</button>
+    </form>
+</body>
+</html>"#,
+        ))
+}
+
+/// Handles both the confirmation page form and one-click unsubscribe requests from mail clients
+#[tracing::instrument(name = "Unsubscribe a subscriber", skip(parameters, pool))]
+pub async fn unsubscribe(
+    parameters: web::Query<UnsubscribeParameters>,
+    pool: web::Data<PgPool>,
+) -> HttpResponse {
+    match mark_subscriber_as_unsubscribed(&pool, &parameters.unsubscribe_token).await {
+        Ok(true) => HttpResponse::Ok()
+            .content_type(ContentType::html())
+            .body("<p>You have been unsubscribed.</p>"),
+        Ok(false) => HttpResponse::Unauthorized().finish(),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[tracing::instrument(name = "Check the unsubscribe token", skip(pool, unsubscribe_token))]
+async fn subscription_exists(pool: &PgPool, unsubscribe_token: &str) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"SELECT id FROM subscriptions WHERE unsubscribe_token = $1"#,
+        unsubscribe_token,
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {e:?}");
+        e
+    })?;
+    Ok(result.is_some())
+}
+
+/// Returns whether a subscriber matched the token.
+///
+/// Emails already queued for the subscriber are cancelled along the way.
+#[tracing::instrument(
+    name = "Mark subscriber as unsubscribed",
+    skip(pool, unsubscribe_token)
+)]
+pub async fn mark_subscriber_as_unsubscribed(
+    pool: &PgPool,
+    unsubscribe_token: &str,
+) -> Result<bool, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    let unsubscribed = sqlx::query!(
+        r#"UPDATE subscriptions SET status = 'unsubscribed' WHERE unsubscribe_token = $1 RETURNING email"#,
+        unsubscribe_token,
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {e:?}");
+        e
+    })?;
+    let Some(unsubscribed) = unsubscribed else {
+        return Ok(false);
+    };
+    cancel_pending_emails(
+        &mut transaction,
+        &unsubscribed.email,
+        "Recipient unsubscribed",
+    )
+    .await?;
+    transaction.commit().await?;
+    Ok(true)
+}
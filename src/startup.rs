@@ -1,17 +1,40 @@
-use crate::configuration::{DatabaseSettings, Settings};
+use crate::authentication::{reject_anonymous_users, reject_logged_out_users, seed_operator};
+use crate::configuration::{
+    ConfirmationTokenMode, ConfirmationTokenSettings, DatabaseSettings, EmailProvider,
+    EmailSettings, Settings,
+};
 use crate::domain::SubscriberEmail;
-use crate::email_client::EmailClient;
-use crate::routes::{health_check, subscribe};
+use crate::email_client::{
+    EmailClient, EmailSender, InMemoryEmailClient, RetryPolicy, SmtpEmailClient,
+};
+use crate::email_delivery_worker::EmailDeliveryWorker;
+use crate::idempotency::idempotency;
+use crate::routes::{
+    admin_dashboard, change_password_form, change_password_submit, confirm, delete_subscriber,
+    get_subscriber, health_check, list_subscribers, log_out, login, login_form,
+    newsletter_issue_status, publish_newsletter, subscribe, unsubscribe, unsubscribe_form,
+    update_subscriber,
+};
+use crate::subscription_cleanup::SubscriptionCleanup;
+use actix_session::storage::CookieSessionStore;
+use actix_session::SessionMiddleware;
+use actix_web::cookie::Key;
 use actix_web::dev::Server;
+use actix_web::middleware::from_fn;
 use actix_web::{web, App, HttpServer};
+use actix_web_flash_messages::storage::CookieMessageStore;
+use actix_web_flash_messages::FlashMessagesFramework;
 use sqlx::postgres::PgPoolOptions;
 use sqlx::PgPool;
 use std::net::TcpListener;
+use std::sync::Arc;
 use tracing_actix_web::TracingLogger;
 
 pub struct Application {
     port: u16,
     server: Server,
+    worker: Option<EmailDeliveryWorker>,
+    cleanup: Option<SubscriptionCleanup>,
 }
 impl Application {
     pub async fn build(configuration: &Settings) -> Result<Self, std::io::Error> {
@@ -24,46 +47,182 @@ impl Application {
             .run(&pool)
             .await
             .expect("Failed to migrate database");
+        seed_operator(&configuration.operator, &pool)
+            .await
+            .expect("Failed to seed the operator account");
 
         // Email Client
-        let base_url = &configuration.email.base_url;
-        let sender =
-            SubscriberEmail::parse(&configuration.email.sender).expect("Failed to parse sender");
-        let api_key = &configuration.email.apikey;
-        let timeout = configuration.email.timeout();
-        let email_client = EmailClient::new(base_url, sender, api_key, timeout)
-            .expect("Failed to create email client");
-        let server = run(listener, pool, email_client)?;
-        Ok(Self { port, server })
+        let email_client = build_email_client(&configuration.email);
+
+        // Email delivery worker
+        let worker = configuration.email.worker.enabled.then(|| {
+            EmailDeliveryWorker::new(
+                pool.clone(),
+                email_client.clone(),
+                &configuration.email.worker,
+            )
+        });
+        // Removal of stale pending subscriptions
+        let subscriptions = &configuration.subscriptions;
+        assert!(
+            subscriptions.confirmation_tokens.mode != ConfirmationTokenMode::Signed
+                || subscriptions.confirmation_tokens.signing_key.is_some(),
+            "No `subscriptions.confirmation_tokens.signing_key` configured to sign the confirmation tokens"
+        );
+        let cleanup = subscriptions
+            .cleanup
+            .enabled
+            .then(|| SubscriptionCleanup::new(pool.clone(), subscriptions));
+        let hmac_secret = configuration
+            .application
+            .hmac_secret
+            .as_deref()
+            .expect("No `application.hmac_secret` configured to sign the session cookies");
+        let server = run(
+            listener,
+            pool,
+            email_client,
+            configuration.application.base_url.clone(),
+            hmac_secret,
+            subscriptions.token_ttl(),
+            subscriptions.confirmation_tokens.clone(),
+        )?;
+        Ok(Self {
+            port,
+            server,
+            worker,
+            cleanup,
+        })
     }
     pub fn port(&self) -> u16 {
         self.port
     }
     pub async fn run_until_stopped(self) -> Result<(), std::io::Error> {
-        self.server.await
+        let worker = self.worker.map(|w| tokio::spawn(w.run_until_stopped()));
+        let cleanup = self.cleanup.map(|c| tokio::spawn(c.run_until_stopped()));
+        let outcome = self.server.await;
+        for task in [worker, cleanup].into_iter().flatten() {
+            task.abort();
+        }
+        outcome
     }
 }
 
+/// Public address of the application, used to build links sent to subscribers
+pub struct ApplicationBaseUrl(pub String);
+
+/// Validity of the confirmation links sent to new subscribers
+pub struct SubscriptionTokenTtl(pub chrono::Duration);
+
 fn run(
     tcp_listener: TcpListener,
     pool: PgPool,
-    email_client: EmailClient,
+    email_client: Arc<dyn EmailSender>,
+    base_url: String,
+    hmac_secret: &str,
+    token_ttl: std::time::Duration,
+    confirmation_tokens: ConfirmationTokenSettings,
 ) -> Result<Server, std::io::Error> {
     let pool = web::Data::new(pool);
-    let email_client = web::Data::new(email_client);
+    let secret_key = Key::from(hmac_secret.as_bytes());
+    let message_store = CookieMessageStore::builder(secret_key.clone()).build();
+    let message_framework = FlashMessagesFramework::builder(message_store).build();
+    let email_client: web::Data<dyn EmailSender> = web::Data::from(email_client);
+    let base_url = web::Data::new(ApplicationBaseUrl(base_url));
+    let token_ttl = web::Data::new(SubscriptionTokenTtl(
+        chrono::Duration::from_std(token_ttl).expect("Subscription token TTL is out of range"),
+    ));
+    let confirmation_tokens = web::Data::new(confirmation_tokens);
     let server = HttpServer::new(move || {
         App::new()
+            .wrap(message_framework.clone())
+            .wrap(SessionMiddleware::new(
+                CookieSessionStore::default(),
+                secret_key.clone(),
+            ))
             .wrap(TracingLogger::default())
             .route("/health_check", web::get().to(health_check))
-            .route("/subscriptions", web::post().to(subscribe))
+            .service(
+                web::resource("/subscriptions")
+                    .wrap(from_fn(idempotency))
+                    .route(web::post().to(subscribe)),
+            )
+            .route("/subscriptions/confirm", web::get().to(confirm))
+            .route(
+                "/subscriptions/unsubscribe",
+                web::get().to(unsubscribe_form),
+            )
+            .route("/subscriptions/unsubscribe", web::post().to(unsubscribe))
+            .service(
+                web::scope("/newsletters")
+                    .wrap(from_fn(idempotency))
+                    .wrap(from_fn(reject_anonymous_users))
+                    .route("", web::post().to(publish_newsletter)),
+            )
+            .route("/login", web::get().to(login_form))
+            .route("/login", web::post().to(login))
+            .service(
+                web::scope("/admin")
+                    .wrap(from_fn(reject_logged_out_users))
+                    .route("/dashboard", web::get().to(admin_dashboard))
+                    .route("/password", web::get().to(change_password_form))
+                    .route("/password", web::post().to(change_password_submit))
+                    .route(
+                        "/newsletters/{newsletter_issue_id}",
+                        web::get().to(newsletter_issue_status),
+                    )
+                    .route("/subscribers", web::get().to(list_subscribers))
+                    .service(
+                        web::resource("/subscribers/{subscriber_id}")
+                            .route(web::get().to(get_subscriber))
+                            .route(web::patch().to(update_subscriber))
+                            .route(web::delete().to(delete_subscriber)),
+                    )
+                    .route("/logout", web::post().to(log_out)),
+            )
             .app_data(pool.clone())
             .app_data(email_client.clone())
+            .app_data(base_url.clone())
+            .app_data(token_ttl.clone())
+            .app_data(confirmation_tokens.clone())
     })
     .listen(tcp_listener)?
     .run();
     Ok(server)
 }
 
+pub fn build_email_client(configuration: &EmailSettings) -> Arc<dyn EmailSender> {
+    let sender = SubscriberEmail::parse(&configuration.sender).expect("Failed to parse sender");
+    let timeout = configuration.timeout();
+    match configuration.provider {
+        EmailProvider::Unisender => {
+            let base_url = &configuration.base_url;
+            let api_key = &configuration.apikey;
+            let email_client = EmailClient::new(
+                base_url,
+                sender,
+                &configuration.sender_name,
+                &configuration.list_id,
+                api_key,
+                timeout,
+            )
+            .expect("Failed to create email client")
+            .with_retry_policy(RetryPolicy::from(&configuration.retry));
+            Arc::new(email_client)
+        }
+        EmailProvider::Smtp => {
+            let smtp = configuration
+                .smtp
+                .as_ref()
+                .expect("Missing SMTP settings for the smtp email provider");
+            let email_client = SmtpEmailClient::new(smtp, sender, timeout)
+                .expect("Failed to create SMTP email client");
+            Arc::new(email_client)
+        }
+        EmailProvider::InMemory => Arc::new(InMemoryEmailClient::new()),
+    }
+}
+
 pub fn get_connection_pool(configuration: &DatabaseSettings) -> PgPool {
     PgPoolOptions::new()
         .acquire_timeout(std::time::Duration::from_secs(2))
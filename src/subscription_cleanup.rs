@@ -0,0 +1,126 @@
+use crate::configuration::{ConfirmationTokenMode, SubscriptionSettings};
+use sqlx::PgPool;
+use std::time::Duration;
+
+/// Periodically deletes the pending subscribers who can no longer confirm, along with
+/// expired confirmation tokens
+#[derive(Debug)]
+pub struct SubscriptionCleanup {
+    pool: PgPool,
+    interval: Duration,
+    mode: ConfirmationTokenMode,
+    token_ttl: chrono::Duration,
+}
+
+/// Rows removed by a single cleanup
+#[derive(Debug, PartialEq, Eq)]
+pub struct CleanupOutcome {
+    pub deleted_tokens: u64,
+    pub deleted_subscribers: u64,
+}
+
+impl SubscriptionCleanup {
+    pub fn new(pool: PgPool, settings: &SubscriptionSettings) -> Self {
+        Self {
+            pool,
+            interval: settings.cleanup.interval(),
+            mode: settings.confirmation_tokens.mode,
+            token_ttl: chrono::Duration::from_std(settings.token_ttl())
+                .expect("Subscription token TTL is out of range"),
+        }
+    }
+    pub async fn run_until_stopped(self) {
+        let mut interval = tokio::time::interval(self.interval);
+        loop {
+            interval.tick().await;
+            // Failures are logged by the deletions, the next tick tries again
+            let _ = match self.mode {
+                ConfirmationTokenMode::Table => delete_stale_subscriptions(&self.pool).await,
+                ConfirmationTokenMode::Signed => {
+                    delete_expired_pending_subscriptions(&self.pool, self.token_ttl).await
+                }
+            };
+        }
+    }
+}
+
+/// Delete expired tokens, then the pending subscribers who have no valid token left
+#[tracing::instrument(name = "Delete stale pending subscriptions", skip(pool), err)]
+pub async fn delete_stale_subscriptions(pool: &PgPool) -> Result<CleanupOutcome, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    let deleted_tokens = sqlx::query!(
+        r#"
+DELETE FROM subscription_tokens
+WHERE expires_at <= now()
+"#
+    )
+    .execute(&mut *transaction)
+    .await?
+    .rows_affected();
+    let deleted_subscribers = sqlx::query!(
+        r#"
+DELETE FROM subscriptions s
+WHERE s.status = 'pending_confirmation'
+  AND NOT EXISTS (SELECT 1 FROM subscription_tokens t WHERE t.subscriber_id = s.id)
+"#
+    )
+    .execute(&mut *transaction)
+    .await?
+    .rows_affected();
+    transaction.commit().await?;
+    tracing::info!(
+        deleted_tokens,
+        deleted_subscribers,
+        "stale subscriptions deleted"
+    );
+    Ok(CleanupOutcome {
+        deleted_tokens,
+        deleted_subscribers,
+    })
+}
+
+/// Delete the pending subscribers who subscribed longer than `token_ttl` ago, for signed
+/// confirmation tokens: their links have all expired, but there is no token table to tell
+#[tracing::instrument(name = "Delete expired pending subscriptions", skip(pool), err)]
+pub async fn delete_expired_pending_subscriptions(
+    pool: &PgPool,
+    token_ttl: chrono::Duration,
+) -> Result<CleanupOutcome, sqlx::Error> {
+    let requested_before = chrono::Utc::now() - token_ttl;
+    let mut transaction = pool.begin().await?;
+    // Tokens left over from the table mode
+    let deleted_tokens = sqlx::query!(
+        r#"
+DELETE FROM subscription_tokens t
+USING subscriptions s
+WHERE t.subscriber_id = s.id
+  AND s.status = 'pending_confirmation'
+  AND s.confirmation_requested_at < $1
+"#,
+        requested_before
+    )
+    .execute(&mut *transaction)
+    .await?
+    .rows_affected();
+    let deleted_subscribers = sqlx::query!(
+        r#"
+DELETE FROM subscriptions
+WHERE status = 'pending_confirmation'
+  AND confirmation_requested_at < $1
+"#,
+        requested_before
+    )
+    .execute(&mut *transaction)
+    .await?
+    .rows_affected();
+    transaction.commit().await?;
+    tracing::info!(
+        deleted_tokens,
+        deleted_subscribers,
+        "expired pending subscriptions deleted"
+    );
+    Ok(CleanupOutcome {
+        deleted_tokens,
+        deleted_subscribers,
+    })
+}
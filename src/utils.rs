@@ -0,0 +1,17 @@
+use actix_web::http::header::LOCATION;
+use actix_web::HttpResponse;
+
+/// Turn any error into a 500 Internal Server Error, preserving it for the logs
+pub fn e500<T>(e: T) -> actix_web::Error
+where
+    T: std::fmt::Debug + std::fmt::Display + 'static,
+{
+    actix_web::error::ErrorInternalServerError(e)
+}
+
+/// Redirect the browser to `location` with a 303 See Other
+pub fn see_other(location: &str) -> HttpResponse {
+    HttpResponse::SeeOther()
+        .insert_header((LOCATION, location))
+        .finish()
+}
@@ -0,0 +1,431 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use chrono::{DateTime, Utc};
+use quickcheck::{Arbitrary, Gen};
+use std::collections::HashSet;
+use uuid::Uuid;
+
+/// Subscribe through the public endpoint and return the id of the new subscriber
+async fn create_subscriber(app: &TestApp, name: &str, email: &str) -> String {
+    app.post_subscriptions_json(&serde_json::json!({ "name": name, "email": email }))
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    sqlx::query!("SELECT id FROM subscriptions WHERE email = $1", email)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id
+        .to_string()
+}
+
+async fn listed_emails(app: &TestApp, query: &str) -> Vec<String> {
+    let response = app.get_admin_subscribers(query).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    body["subscribers"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|s| s["email"].as_str().unwrap().to_string())
+        .collect()
+}
+
+#[actix_web::test]
+async fn you_must_be_logged_in_to_manage_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber_id = Uuid::new_v4().to_string();
+    // Act & Assert
+    assert_is_redirect_to(&app.get_admin_subscribers("").await, "/login");
+    assert_is_redirect_to(&app.get_admin_subscriber(&subscriber_id).await, "/login");
+    let patch = serde_json::json!({ "name": "Ursula" });
+    assert_is_redirect_to(
+        &app.patch_admin_subscriber(&subscriber_id, &patch).await,
+        "/login",
+    );
+    assert_is_redirect_to(&app.delete_admin_subscriber(&subscriber_id).await, "/login");
+}
+
+#[actix_web::test]
+async fn subscribers_are_listed_oldest_first_with_a_cursor_to_the_next_page() {
+    // Arrange
+    let app = spawn_app().await;
+    for i in 0..3 {
+        create_subscriber(&app, "le guin", &format!("reader{i}@gmail.com")).await;
+    }
+    app.login().await;
+    // Act
+    let response = app.get_admin_subscribers("page_size=2").await;
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["subscribers"].as_array().unwrap().len(), 2);
+    assert_eq!(body["subscribers"][0]["email"], "reader0@gmail.com");
+    assert_eq!(body["subscribers"][0]["status"], "pending_confirmation");
+    assert_eq!(body["subscribers"][1]["email"], "reader1@gmail.com");
+    let next_cursor = body["next_cursor"].as_str().unwrap();
+    let response = app
+        .get_admin_subscribers(&format!("page_size=2&cursor={next_cursor}"))
+        .await;
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["subscribers"].as_array().unwrap().len(), 1);
+    assert_eq!(body["subscribers"][0]["email"], "reader2@gmail.com");
+    assert!(body["next_cursor"].is_null());
+}
+
+/// Subscribers present before paging starts, and those inserted between two pages
+#[derive(Debug, Clone)]
+struct PagingScenario {
+    page_size: i64,
+    initial: Vec<i64>,
+    inserted_between_pages: Vec<Vec<i64>>,
+}
+
+impl Arbitrary for PagingScenario {
+    fn arbitrary(g: &mut Gen) -> Self {
+        // Few distinct seconds, so that many subscribers share the same `subscribed_at`
+        let offsets = |g: &mut Gen| -> Vec<i64> {
+            Vec::<u8>::arbitrary(g)
+                .into_iter()
+                .map(|offset| i64::from(offset % 8))
+                .collect()
+        };
+        Self {
+            page_size: i64::from(u8::arbitrary(g) % 4) + 1,
+            initial: offsets(g),
+            inserted_between_pages: (0..g.size()).map(|_| offsets(g)).collect(),
+        }
+    }
+}
+
+async fn insert_subscribers_at(app: &TestApp, epoch: DateTime<Utc>, offsets: &[i64]) -> Vec<Uuid> {
+    let mut ids = Vec::new();
+    for offset in offsets {
+        let id = Uuid::new_v4();
+        sqlx::query!(
+            r#"
+INSERT INTO subscriptions (id, email, name, subscribed_at, status, unsubscribe_token)
+VALUES ($1, $2, 'le guin', $3, 'confirmed', $4)
+"#,
+            id,
+            format!("{}@gmail.com", id.simple()),
+            epoch + chrono::Duration::seconds(*offset),
+            id.simple().to_string()
+        )
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+        ids.push(id);
+    }
+    ids
+}
+
+#[actix_web::test]
+async fn paging_through_concurrent_inserts_never_skips_or_duplicates_subscribers() {
+    let app = spawn_app().await;
+    app.login().await;
+    let mut g = Gen::new(6);
+    for _ in 0..30 {
+        // Arrange
+        let scenario = PagingScenario::arbitrary(&mut g);
+        sqlx::query!("DELETE FROM subscriptions")
+            .execute(&app.db_pool)
+            .await
+            .unwrap();
+        let epoch = Utc::now();
+        let initial = insert_subscribers_at(&app, epoch, &scenario.initial).await;
+        let mut inserts = scenario.inserted_between_pages.iter();
+        let mut seen: Vec<(DateTime<Utc>, Uuid)> = Vec::new();
+        let mut query = format!("page_size={}", scenario.page_size);
+        // Act
+        loop {
+            let body: serde_json::Value = app
+                .get_admin_subscribers(&query)
+                .await
+                .json()
+                .await
+                .unwrap();
+            for subscriber in body["subscribers"].as_array().unwrap() {
+                seen.push((
+                    subscriber["subscribed_at"]
+                        .as_str()
+                        .unwrap()
+                        .parse()
+                        .unwrap(),
+                    subscriber["id"].as_str().unwrap().parse().unwrap(),
+                ));
+            }
+            let Some(next_cursor) = body["next_cursor"].as_str() else {
+                break;
+            };
+            query = format!("page_size={}&cursor={next_cursor}", scenario.page_size);
+            if let Some(offsets) = inserts.next() {
+                insert_subscribers_at(&app, epoch, offsets).await;
+            }
+        }
+        // Assert
+        assert!(
+            seen.windows(2).all(|pair| pair[0] < pair[1]),
+            "Subscribers were duplicated or out of order in {scenario:?}"
+        );
+        let seen_ids: HashSet<_> = seen.iter().map(|(_, id)| *id).collect();
+        assert!(
+            initial.iter().all(|id| seen_ids.contains(id)),
+            "Subscribers were skipped in {scenario:?}"
+        );
+    }
+}
+
+#[actix_web::test]
+async fn subscribers_can_be_filtered_by_status_and_email() {
+    // Arrange
+    let app = spawn_app().await;
+    let confirmed_id = create_subscriber(&app, "le guin", "ursula@gmail.com").await;
+    create_subscriber(&app, "le guin", "ursula@example.com").await;
+    create_subscriber(&app, "butler", "octavia@gmail.com").await;
+    create_subscriber(&app, "under score", "a_b@example.com").await;
+    sqlx::query!(
+        "UPDATE subscriptions SET status = 'confirmed' WHERE id = $1",
+        Uuid::parse_str(&confirmed_id).unwrap()
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app.login().await;
+    // Act & Assert
+    assert_eq!(
+        listed_emails(&app, "status=confirmed").await,
+        vec!["ursula@gmail.com"]
+    );
+    assert_eq!(
+        listed_emails(&app, "email=URSULA").await,
+        vec!["ursula@gmail.com", "ursula@example.com"]
+    );
+    assert_eq!(
+        listed_emails(&app, "status=pending_confirmation&email=gmail").await,
+        vec!["octavia@gmail.com"]
+    );
+    // Wildcards are matched literally
+    assert_eq!(
+        listed_emails(&app, "email=_").await,
+        vec!["a_b@example.com"]
+    );
+}
+
+#[actix_web::test]
+async fn invalid_listing_parameters_are_rejected_with_a_400() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login().await;
+    let test_cases = [
+        ("status=deleted", "status"),
+        ("cursor=not-a-cursor", "cursor"),
+        ("page_size=0", "page_size"),
+        ("page_size=10000", "page_size"),
+    ];
+    for (query, field) in test_cases {
+        // Act
+        let response = app.get_admin_subscribers(query).await;
+        // Assert
+        assert_eq!(response.status().as_u16(), 400, "for {query}");
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["errors"][0]["field"], field, "for {query}");
+    }
+}
+
+#[actix_web::test]
+async fn a_subscriber_can_be_fetched_by_id() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber_id = create_subscriber(&app, "le guin", "ursula@gmail.com").await;
+    app.login().await;
+    // Act
+    let response = app.get_admin_subscriber(&subscriber_id).await;
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["id"], subscriber_id.as_str());
+    assert_eq!(body["name"], "le guin");
+    assert_eq!(body["email"], "ursula@gmail.com");
+    assert_eq!(body["status"], "pending_confirmation");
+    assert!(body["subscribed_at"].is_string());
+}
+
+#[actix_web::test]
+async fn unknown_subscribers_are_reported_with_a_404() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login().await;
+    let subscriber_id = Uuid::new_v4().to_string();
+    // Act & Assert
+    assert_eq!(
+        app.get_admin_subscriber(&subscriber_id)
+            .await
+            .status()
+            .as_u16(),
+        404
+    );
+    let patch = serde_json::json!({ "name": "Ursula" });
+    assert_eq!(
+        app.patch_admin_subscriber(&subscriber_id, &patch)
+            .await
+            .status()
+            .as_u16(),
+        404
+    );
+    assert_eq!(
+        app.delete_admin_subscriber(&subscriber_id)
+            .await
+            .status()
+            .as_u16(),
+        404
+    );
+}
+
+#[actix_web::test]
+async fn patch_updates_only_the_given_fields() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber_id = create_subscriber(&app, "le guin", "ursula@gmail.com").await;
+    app.login().await;
+    // Act
+    let patch = serde_json::json!({ "name": "Ursula K. Le Guin", "status": "confirmed" });
+    let response = app.patch_admin_subscriber(&subscriber_id, &patch).await;
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["name"], "Ursula K. Le Guin");
+    assert_eq!(body["status"], "confirmed");
+    assert_eq!(body["email"], "ursula@gmail.com");
+    let saved = sqlx::query!("SELECT name, email, status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.name, "Ursula K. Le Guin");
+    assert_eq!(saved.email, "ursula@gmail.com");
+    assert_eq!(saved.status, "confirmed");
+}
+
+#[actix_web::test]
+async fn patch_rejects_invalid_fields_with_a_400() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber_id = create_subscriber(&app, "le guin", "ursula@gmail.com").await;
+    app.login().await;
+    // Act
+    let patch = serde_json::json!({ "name": "", "email": "not-an-email", "status": "deleted" });
+    let response = app.patch_admin_subscriber(&subscriber_id, &patch).await;
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+    let body: serde_json::Value = response.json().await.unwrap();
+    let fields: Vec<_> = body["errors"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|e| e["field"].as_str().unwrap())
+        .collect();
+    assert_eq!(fields, vec!["name", "email", "status"]);
+    let saved = sqlx::query!("SELECT name FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.name, "le guin");
+}
+
+#[actix_web::test]
+async fn patch_rejects_setting_a_subscriber_back_to_pending_confirmation() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber_id = create_subscriber(&app, "le guin", "ursula@gmail.com").await;
+    app.login().await;
+    let patch = serde_json::json!({ "status": "confirmed" });
+    app.patch_admin_subscriber(&subscriber_id, &patch).await;
+    // Act
+    let patch = serde_json::json!({ "status": "pending_confirmation" });
+    let response = app.patch_admin_subscriber(&subscriber_id, &patch).await;
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["errors"][0]["field"], "status");
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "confirmed");
+}
+
+#[actix_web::test]
+async fn patch_to_unsubscribed_cancels_the_emails_queued_for_the_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber_id = create_subscriber(&app, "le guin", "ursula@gmail.com").await;
+    create_subscriber(&app, "butler", "octavia@gmail.com").await;
+    app.login().await;
+    // Act
+    let patch = serde_json::json!({ "status": "unsubscribed" });
+    let response = app.patch_admin_subscriber(&subscriber_id, &patch).await;
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let queued = sqlx::query!(
+        "SELECT recipient_email, status FROM email_delivery_queue ORDER BY recipient_email"
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(queued.len(), 2);
+    assert_eq!(queued[0].recipient_email, "octavia@gmail.com");
+    assert_ne!(queued[0].status, "cancelled");
+    assert_eq!(queued[1].recipient_email, "ursula@gmail.com");
+    assert_eq!(queued[1].status, "cancelled");
+}
+
+#[actix_web::test]
+async fn patch_rejects_an_email_used_by_another_subscriber_with_a_409() {
+    // Arrange
+    let app = spawn_app().await;
+    create_subscriber(&app, "le guin", "ursula@gmail.com").await;
+    let subscriber_id = create_subscriber(&app, "butler", "octavia@gmail.com").await;
+    app.login().await;
+    // Act
+    let patch = serde_json::json!({ "email": "ursula@gmail.com" });
+    let response = app.patch_admin_subscriber(&subscriber_id, &patch).await;
+    // Assert
+    assert_eq!(response.status().as_u16(), 409);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["errors"][0]["field"], "email");
+}
+
+#[actix_web::test]
+async fn delete_removes_the_subscriber_and_their_tokens() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber_id = create_subscriber(&app, "le guin", "ursula@gmail.com").await;
+    let other_id = create_subscriber(&app, "butler", "octavia@gmail.com").await;
+    app.login().await;
+    // Act
+    let response = app.delete_admin_subscriber(&subscriber_id).await;
+    // Assert
+    assert_eq!(response.status().as_u16(), 204);
+    assert_eq!(
+        app.get_admin_subscriber(&subscriber_id)
+            .await
+            .status()
+            .as_u16(),
+        404
+    );
+    let tokens = sqlx::query!("SELECT subscriber_id FROM subscription_tokens")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(tokens.len(), 1);
+    assert_eq!(tokens[0].subscriber_id.to_string(), other_id);
+    // The confirmation email is never sent
+    let queued = sqlx::query!(
+        "SELECT status FROM email_delivery_queue WHERE recipient_email = 'ursula@gmail.com'"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(queued.status, "cancelled");
+}
@@ -0,0 +1,104 @@
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::{email_sent_response, spawn_app};
+
+#[actix_web::test]
+async fn subscribe_queues_the_confirmation_email_instead_of_sending_it() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(any())
+        .respond_with(email_sent_response())
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    // Act
+    let response = app.post_subscriptions(body.into()).await.unwrap();
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let queued = sqlx::query!("SELECT recipient_email, status FROM email_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch queued email.");
+    assert_eq!(queued.recipient_email, "ursula_le_guin@gmail.com");
+    assert_eq!(queued.status, "pending");
+}
+
+#[actix_web::test]
+async fn dispatching_delivers_queued_emails_and_records_them_as_sent() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(path("/ru/api/sendEmail"))
+        .and(method("POST"))
+        .respond_with(email_sent_response())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions(body.into()).await.unwrap();
+    // Act
+    app.dispatch_all_pending_emails().await;
+    // Assert
+    let queued = sqlx::query!(
+        "SELECT status, n_attempts, last_error, completed_at FROM email_delivery_queue"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("Failed to fetch queued email.");
+    assert_eq!(queued.status, "sent");
+    assert_eq!(queued.n_attempts, 1);
+    assert!(queued.last_error.is_none());
+    assert!(queued.completed_at.is_some());
+}
+
+#[actix_web::test]
+async fn transient_failures_are_rescheduled_for_a_later_attempt() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(503))
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions(body.into()).await.unwrap();
+    // Act
+    app.dispatch_all_pending_emails().await;
+    // Assert
+    let queued = sqlx::query!(
+        "SELECT status, n_attempts, last_error, execute_after > now() AS \"delayed!\" FROM email_delivery_queue"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("Failed to fetch queued email.");
+    assert_eq!(queued.status, "pending");
+    assert_eq!(queued.n_attempts, 1);
+    assert!(queued.last_error.unwrap().contains("503"));
+    assert!(queued.delayed);
+}
+
+#[actix_web::test]
+async fn permanent_failures_are_recorded_as_failed() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(400))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions(body.into()).await.unwrap();
+    // Act
+    app.dispatch_all_pending_emails().await;
+    // Assert
+    let queued = sqlx::query!(
+        "SELECT status, n_attempts, last_error, completed_at FROM email_delivery_queue"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("Failed to fetch queued email.");
+    assert_eq!(queued.status, "failed");
+    assert_eq!(queued.n_attempts, 1);
+    assert!(queued.last_error.unwrap().contains("400"));
+    assert!(queued.completed_at.is_some());
+}
@@ -1,15 +1,60 @@
 use once_cell::sync::Lazy;
 use reqwest::Response;
 use sqlx::{Connection, Executor, PgConnection, PgPool};
-use wiremock::MockServer;
-use zero2prod::startup::{get_connection_pool, Application};
+use uuid::Uuid;
+use wiremock::{MockServer, ResponseTemplate};
+use zero2prod::authentication::compute_password_hash;
+use zero2prod::configuration::{EmailProvider, Settings};
+use zero2prod::email_delivery_worker::{EmailDeliveryWorker, ExecutionOutcome};
+use zero2prod::startup::{build_email_client, get_connection_pool, Application};
 use zero2prod::telemetry::init_subscriber;
 
 pub struct TestApp {
     pub address: String,
+    pub port: u16,
     pub db_pool: PgPool,
     pub email_server: MockServer,
+    pub email_worker: EmailDeliveryWorker,
+    pub test_user: TestUser,
+    /// Client keeping cookies between requests and never following redirects
+    pub api_client: reqwest::Client,
 }
+
+/// Operator account stored in the `users` table of the test database
+pub struct TestUser {
+    pub user_id: Uuid,
+    pub username: String,
+    pub password: String,
+}
+
+impl TestUser {
+    pub fn generate() -> Self {
+        Self {
+            user_id: Uuid::new_v4(),
+            username: Uuid::new_v4().to_string(),
+            password: Uuid::new_v4().to_string(),
+        }
+    }
+    async fn store(&self, pool: &PgPool) {
+        let password_hash = compute_password_hash(&self.password).unwrap();
+        sqlx::query!(
+            "INSERT INTO users (user_id, username, password_hash) VALUES ($1, $2, $3)",
+            self.user_id,
+            self.username,
+            password_hash,
+        )
+        .execute(pool)
+        .await
+        .expect("Failed to store test user.");
+    }
+}
+
+/// Confirmation links embedded in the request sent to the email API
+pub struct ConfirmationLinks {
+    pub html: reqwest::Url,
+    pub plain_text: reqwest::Url,
+}
+
 impl TestApp {
     pub async fn post_subscriptions(&self, body: String) -> Result<Response, reqwest::Error> {
         reqwest::Client::new()
@@ -19,6 +64,230 @@ impl TestApp {
             .send()
             .await
     }
+    /// Subscribe with a JSON body, asking for a JSON response
+    pub async fn post_subscriptions_json(
+        &self,
+        body: &serde_json::Value,
+    ) -> Result<Response, reqwest::Error> {
+        reqwest::Client::new()
+            .post(format!("{}/subscriptions", &self.address))
+            .header("Accept", "application/json")
+            .json(body)
+            .send()
+            .await
+    }
+    pub async fn post_newsletters(&self, body: &serde_json::Value) -> reqwest::Response {
+        self.newsletters_request(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn post_newsletters_with_idempotency_key(
+        &self,
+        body: &serde_json::Value,
+        idempotency_key: &str,
+    ) -> reqwest::Response {
+        self.newsletters_request(body)
+            .header("Idempotency-Key", idempotency_key)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    fn newsletters_request(&self, body: &serde_json::Value) -> reqwest::RequestBuilder {
+        reqwest::Client::new()
+            .post(format!("{}/newsletters", &self.address))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .json(body)
+    }
+    pub async fn get_login_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/login", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+    pub async fn post_login<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/login", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    /// Log in through the login form with the test user credentials
+    pub async fn login(&self) {
+        self.post_login(&serde_json::json!({
+            "username": &self.test_user.username,
+            "password": &self.test_user.password
+        }))
+        .await;
+    }
+    pub async fn get_admin_dashboard(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/dashboard", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn get_admin_dashboard_html(&self) -> String {
+        self.get_admin_dashboard().await.text().await.unwrap()
+    }
+    pub async fn get_change_password(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/password", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn get_change_password_html(&self) -> String {
+        self.get_change_password().await.text().await.unwrap()
+    }
+    pub async fn post_change_password<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/password", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn get_admin_subscribers(&self, query: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/subscribers?{query}", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn get_admin_subscriber(&self, subscriber_id: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!(
+                "{}/admin/subscribers/{subscriber_id}",
+                &self.address
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn patch_admin_subscriber<Body>(
+        &self,
+        subscriber_id: &str,
+        body: &Body,
+    ) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .patch(format!(
+                "{}/admin/subscribers/{subscriber_id}",
+                &self.address
+            ))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn delete_admin_subscriber(&self, subscriber_id: &str) -> reqwest::Response {
+        self.api_client
+            .delete(format!(
+                "{}/admin/subscribers/{subscriber_id}",
+                &self.address
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn get_newsletter_issue_status(
+        &self,
+        newsletter_issue_id: &str,
+    ) -> reqwest::Response {
+        self.api_client
+            .get(format!(
+                "{}/admin/newsletters/{newsletter_issue_id}",
+                &self.address
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn post_logout(&self) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/logout", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    /// Extract the confirmation links from the request intercepted by the email server
+    pub fn get_confirmation_links(&self, email_request: &wiremock::Request) -> ConfirmationLinks {
+        let get_link = |field: &str| {
+            let body = form_urlencoded::parse(&email_request.body)
+                .find(|(key, _)| key == field)
+                .map(|(_, value)| value.into_owned())
+                .unwrap_or_else(|| panic!("No {field} in the email request"));
+            let links: Vec<_> = linkify::LinkFinder::new()
+                .links(&body)
+                .filter(|l| *l.kind() == linkify::LinkKind::Url)
+                .filter(|l| l.as_str().contains("/subscriptions/confirm"))
+                .collect();
+            assert_eq!(links.len(), 1);
+            let mut confirmation_link = reqwest::Url::parse(links[0].as_str()).unwrap();
+            // Make sure we don't call random APIs on the web
+            assert_eq!(confirmation_link.host_str().unwrap(), "127.0.0.1");
+            confirmation_link.set_port(Some(self.port)).unwrap();
+            confirmation_link
+        };
+        ConfirmationLinks {
+            html: get_link("body"),
+            plain_text: get_link("text_body"),
+        }
+    }
+    /// Extract the unsubscribe link from the footer of the request intercepted by the email server
+    pub fn get_unsubscribe_link(&self, email_request: &wiremock::Request) -> reqwest::Url {
+        let body = form_urlencoded::parse(&email_request.body)
+            .find(|(key, _)| key == "text_body")
+            .map(|(_, value)| value.into_owned())
+            .expect("No text_body in the email request");
+        let link = linkify::LinkFinder::new()
+            .links(&body)
+            .find(|l| l.as_str().contains("/subscriptions/unsubscribe"))
+            .expect("No unsubscribe link in the email");
+        let mut unsubscribe_link = reqwest::Url::parse(link.as_str()).unwrap();
+        assert_eq!(unsubscribe_link.host_str().unwrap(), "127.0.0.1");
+        unsubscribe_link.set_port(Some(self.port)).unwrap();
+        unsubscribe_link
+    }
+    /// Check that every queued email ended up delivered
+    pub async fn assert_all_emails_were_sent(&self) {
+        let statuses: Vec<String> = sqlx::query_scalar!("SELECT status FROM email_delivery_queue")
+            .fetch_all(&self.db_pool)
+            .await
+            .expect("Failed to fetch queued emails.");
+        assert!(!statuses.is_empty(), "No email was queued");
+        assert!(
+            statuses.iter().all(|status| status == "sent"),
+            "Not every email was sent: {statuses:?}"
+        );
+    }
+    /// Deliver every queued email that is due, as the background worker would
+    pub async fn dispatch_all_pending_emails(&self) {
+        loop {
+            if let ExecutionOutcome::EmptyQueue = self
+                .email_worker
+                .try_execute_task()
+                .await
+                .expect("Failed to execute email delivery task")
+            {
+                break;
+            }
+        }
+    }
 }
 static TRACING: Lazy<()> = Lazy::new(|| {
     if std::env::var("TEST_LOG").is_ok() {
@@ -28,32 +297,82 @@ static TRACING: Lazy<()> = Lazy::new(|| {
     }
 });
 pub async fn spawn_app() -> TestApp {
+    spawn_app_with(|_| {}).await
+}
+
+/// Spawn the application after adjusting the test configuration
+pub async fn spawn_app_with(configure: impl FnOnce(&mut Settings)) -> TestApp {
     Lazy::force(&TRACING);
     let email_server = MockServer::start().await;
     let mut configuration = zero2prod::configuration::get().expect("failed to fetch configuration");
     configuration.application.port = 0;
-    configuration.database.database_name = String::from("test_newsletters");
+    // Every test gets its own database so that queued emails are never shared
+    configuration.database.database_name = format!("test_newsletters_{}", Uuid::new_v4().simple());
+    configuration.email.provider = EmailProvider::Unisender;
     configuration.email.base_url = email_server.uri();
+    // Keep the email client retries quick
+    configuration.email.retry.base_delay = 1;
+    configuration.email.retry.max_delay = 10;
+    // Tests dispatch queued emails and clean up subscriptions themselves
+    configuration.email.worker.enabled = false;
+    configuration.subscriptions.cleanup.enabled = false;
+    // Secrets are not part of the configuration files
+    configuration.application.hmac_secret = Some(Uuid::new_v4().simple().to_string().repeat(2));
+    configuration.subscriptions.confirmation_tokens.signing_key = Some(Uuid::new_v4().to_string());
+    configuration.operator.password = Some(Uuid::new_v4().to_string());
+    configure(&mut configuration);
     configure_database(&configuration.database).await;
     let application = Application::build(&configuration)
         .await
         .expect("failed to build application");
-    let address = format!("http://127.0.0.1:{port}", port = application.port());
+    let port = application.port();
+    let address = format!("http://127.0.0.1:{port}");
     tokio::spawn(application.run_until_stopped());
+    let db_pool = get_connection_pool(&configuration.database);
+    let email_worker = EmailDeliveryWorker::new(
+        db_pool.clone(),
+        build_email_client(&configuration.email),
+        &configuration.email.worker,
+    );
+    let test_user = TestUser::generate();
+    test_user.store(&db_pool).await;
+    let api_client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .cookie_store(true)
+        .build()
+        .unwrap();
     TestApp {
         address,
-        db_pool: get_connection_pool(&configuration.database),
+        port,
+        db_pool,
         email_server,
+        email_worker,
+        test_user,
+        api_client,
     }
 }
+/// A successful response of the Unisender API
+pub fn email_sent_response() -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_json(serde_json::json!({
+        "result": {
+          "email_id": "some id"
+        }
+    }))
+}
+
+pub fn assert_is_redirect_to(response: &reqwest::Response, location: &str) {
+    assert_eq!(response.status().as_u16(), 303);
+    assert_eq!(response.headers().get("Location").unwrap(), location);
+}
+
 pub async fn configure_database(config: &zero2prod::configuration::DatabaseSettings) {
     let mut connection = PgConnection::connect_with(&config.without_db())
         .await
         .expect("Failed to connect to Postgres");
-    match connection.execute("CREATE DATABASE test_newsletters").await {
-        Ok(_) => tracing::info!("Database for tests created"),
-        Err(e) => tracing::error!("Failed to create database for tests: {e:?}"),
-    }
+    connection
+        .execute(format!(r#"CREATE DATABASE "{}";"#, config.database_name).as_str())
+        .await
+        .expect("Failed to create database for tests");
     let pool = PgPool::connect_with(config.with_db())
         .await
         .expect("Failed to connect to Postgres");
@@ -0,0 +1,207 @@
+use crate::helpers::{email_sent_response, spawn_app, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::Mock;
+
+fn newsletter_request_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    })
+}
+
+async fn post_subscriptions_with_idempotency_key(
+    app: &TestApp,
+    idempotency_key: &str,
+) -> reqwest::Response {
+    post_subscriptions_as(app, idempotency_key, "ursula_le_guin%40gmail.com").await
+}
+
+async fn post_subscriptions_as(
+    app: &TestApp,
+    idempotency_key: &str,
+    email: &str,
+) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("Accept", "application/json")
+        .header("Idempotency-Key", idempotency_key)
+        .body(format!("name=le%20guin&email={email}"))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn create_confirmed_subscriber(app: &TestApp) {
+    let _mock_guard = Mock::given(path("/ru/api/sendEmail"))
+        .and(method("POST"))
+        .respond_with(email_sent_response())
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+#[actix_web::test]
+async fn newsletter_publishing_is_idempotent() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/ru/api/sendEmail"))
+        .and(method("POST"))
+        .respond_with(email_sent_response())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let idempotency_key = Uuid::new_v4().to_string();
+    // Act - Part 1 - Publish the newsletter
+    let response = app
+        .post_newsletters_with_idempotency_key(&newsletter_request_body(), &idempotency_key)
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    // Act - Part 2 - Retry the same request
+    let response = app
+        .post_newsletters_with_idempotency_key(&newsletter_request_body(), &idempotency_key)
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    app.dispatch_all_pending_emails().await;
+    app.assert_all_emails_were_sent().await;
+    // Mock verifies on Drop that we have sent the newsletter email once
+}
+
+#[actix_web::test]
+async fn concurrent_newsletter_publishing_is_handled_gracefully() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/ru/api/sendEmail"))
+        .and(method("POST"))
+        .respond_with(email_sent_response())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let idempotency_key = Uuid::new_v4().to_string();
+    let body = newsletter_request_body();
+    // Act - Submit two newsletter forms concurrently
+    let (response1, response2) = tokio::join!(
+        app.post_newsletters_with_idempotency_key(&body, &idempotency_key),
+        app.post_newsletters_with_idempotency_key(&body, &idempotency_key)
+    );
+    // Assert
+    assert_eq!(response1.status(), response2.status());
+    assert_eq!(
+        response1.text().await.unwrap(),
+        response2.text().await.unwrap()
+    );
+    app.dispatch_all_pending_emails().await;
+    app.assert_all_emails_were_sent().await;
+    // Mock verifies on Drop that we have sent the newsletter email once
+}
+
+#[actix_web::test]
+async fn retried_subscriptions_send_a_single_confirmation_email() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/ru/api/sendEmail"))
+        .and(method("POST"))
+        .respond_with(email_sent_response())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let idempotency_key = Uuid::new_v4().to_string();
+    // Act
+    let response1 = post_subscriptions_with_idempotency_key(&app, &idempotency_key).await;
+    let response2 = post_subscriptions_with_idempotency_key(&app, &idempotency_key).await;
+    app.dispatch_all_pending_emails().await;
+    // Assert
+    assert_eq!(response1.status().as_u16(), 200);
+    assert_eq!(response2.status().as_u16(), 200);
+    app.assert_all_emails_were_sent().await;
+    // Mock verifies on Drop that we have sent the confirmation email once
+}
+
+#[actix_web::test]
+async fn saved_responses_are_replayed_with_their_headers() {
+    // Arrange
+    let app = spawn_app().await;
+    let idempotency_key = Uuid::new_v4().to_string();
+    let body = "name=&email=ursula_le_guin%40gmail.com";
+    let post = || {
+        reqwest::Client::new()
+            .post(format!("{}/subscriptions", &app.address))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .header("Idempotency-Key", &idempotency_key)
+            .body(body)
+            .send()
+    };
+    // Act
+    let response1 = post().await.unwrap();
+    let response2 = post().await.unwrap();
+    // Assert
+    assert_eq!(response1.status().as_u16(), 400);
+    assert_eq!(response2.status().as_u16(), 400);
+    assert_eq!(
+        response1.headers().get("Content-Type"),
+        response2.headers().get("Content-Type")
+    );
+    assert_eq!(
+        response1.text().await.unwrap(),
+        response2.text().await.unwrap()
+    );
+}
+
+#[actix_web::test]
+async fn a_key_reused_for_a_different_subscription_is_rejected_with_a_422() {
+    // Arrange
+    let app = spawn_app().await;
+    let idempotency_key = Uuid::new_v4().to_string();
+    let response1 = post_subscriptions_as(&app, &idempotency_key, "ursula%40gmail.com").await;
+    assert_eq!(response1.status().as_u16(), 200);
+    let first_body = response1.text().await.unwrap();
+    // Act
+    let response2 = post_subscriptions_as(&app, &idempotency_key, "octavia%40gmail.com").await;
+    // Assert
+    assert_eq!(response2.status().as_u16(), 422);
+    let body = response2.text().await.unwrap();
+    assert_ne!(body, first_body);
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.len(), 1);
+    assert_eq!(saved[0].email, "ursula@gmail.com");
+}
+
+#[actix_web::test]
+async fn invalid_idempotency_keys_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let too_long = "a".repeat(51);
+    let test_cases = vec![("", "empty"), (too_long.as_str(), "too long")];
+    for (idempotency_key, description) in test_cases {
+        // Act
+        let response = post_subscriptions_with_idempotency_key(&app, idempotency_key).await;
+        // Assert
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not fail with 400 Bad Request when the idempotency key was {}.",
+            description
+        );
+    }
+}
@@ -0,0 +1,68 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with};
+
+#[actix_web::test]
+async fn an_error_flash_message_is_set_on_failure() {
+    // Arrange
+    let app = spawn_app().await;
+    let login_body = serde_json::json!({
+        "username": "random-username",
+        "password": "random-password"
+    });
+    // Act - Part 1 - Try to login
+    let response = app.post_login(&login_body).await;
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    // Act - Part 2 - Follow the redirect
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("<p><i>Authentication failed</i></p>"));
+    // Act - Part 3 - Reload the login page
+    let html_page = app.get_login_html().await;
+    assert!(!html_page.contains("Authentication failed"));
+}
+
+#[actix_web::test]
+async fn redirect_to_admin_dashboard_after_login_success() {
+    // Arrange
+    let app = spawn_app().await;
+    let login_body = serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password
+    });
+    // Act - Part 1 - Login
+    let response = app.post_login(&login_body).await;
+    // Assert
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    // Act - Part 2 - Follow the redirect
+    let html_page = app.get_admin_dashboard_html().await;
+    assert!(html_page.contains(&format!("Welcome {}", app.test_user.username)));
+}
+
+#[actix_web::test]
+async fn the_operator_is_seeded_from_the_configuration() {
+    // Arrange
+    let app = spawn_app_with(|configuration| {
+        configuration.operator.username = "ursula".into();
+        configuration.operator.password = Some("a-configured-password".into());
+    })
+    .await;
+    let login_body = serde_json::json!({
+        "username": "ursula",
+        "password": "a-configured-password"
+    });
+    // Act
+    let response = app.post_login(&login_body).await;
+    // Assert
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[actix_web::test]
+#[should_panic(expected = "Failed to seed the operator account")]
+async fn the_application_does_not_start_without_an_operator_password() {
+    spawn_app_with(|configuration| configuration.operator.password = None).await;
+}
+
+#[actix_web::test]
+#[should_panic(expected = "No `application.hmac_secret` configured")]
+async fn the_application_does_not_start_without_an_hmac_secret() {
+    spawn_app_with(|configuration| configuration.application.hmac_secret = None).await;
+}
@@ -1,3 +1,13 @@
+mod admin_dashboard;
+mod admin_subscribers;
+mod change_password;
+mod email_delivery;
 mod health_check;
 mod helpers;
+mod idempotency;
+mod login;
+mod newsletters;
+mod subscription_cleanup;
 mod subscriptions;
+mod subscriptions_confirm;
+mod subscriptions_unsubscribe;
@@ -0,0 +1,335 @@
+use crate::helpers::{
+    assert_is_redirect_to, email_sent_response, spawn_app, ConfirmationLinks, TestApp,
+};
+use uuid::Uuid;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+fn newsletter_request_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    })
+}
+
+/// Subscribe a new user and return the links of the confirmation email, without clicking them
+async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    let _mock_guard = Mock::given(path("/ru/api/sendEmail"))
+        .and(method("POST"))
+        .respond_with(email_sent_response())
+        .named("Create unconfirmed subscriber")
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.post_subscriptions(body.into())
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+    let email_request = &app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    app.get_confirmation_links(email_request)
+}
+
+async fn create_confirmed_subscriber(app: &TestApp) {
+    let confirmation_links = create_unconfirmed_subscriber(app).await;
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+#[actix_web::test]
+async fn newsletters_are_not_delivered_to_unconfirmed_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+    create_unconfirmed_subscriber(&app).await;
+    Mock::given(any())
+        .respond_with(email_sent_response())
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    // Act
+    let response = app.post_newsletters(&newsletter_request_body()).await;
+    app.dispatch_all_pending_emails().await;
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    // Mock verifies on Drop that we haven't sent the newsletter email
+}
+
+#[actix_web::test]
+async fn newsletters_are_delivered_to_confirmed_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/ru/api/sendEmail"))
+        .and(method("POST"))
+        .respond_with(email_sent_response())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    // Act
+    let response = app.post_newsletters(&newsletter_request_body()).await;
+    app.dispatch_all_pending_emails().await;
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    app.assert_all_emails_were_sent().await;
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let field = |name: &str| {
+        form_urlencoded::parse(&email_request.body)
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.into_owned())
+    };
+    assert_eq!(field("subject").as_deref(), Some("Newsletter title"));
+    let text_body = field("text_body").unwrap();
+    assert!(text_body.starts_with("Newsletter body as plain text"));
+    assert!(text_body.contains("/subscriptions/unsubscribe?unsubscribe_token="));
+}
+
+#[actix_web::test]
+async fn confirmed_subscribers_with_an_invalid_stored_email_are_skipped() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    sqlx::query!(
+        r#"
+INSERT INTO subscriptions (id, email, name, subscribed_at, status, unsubscribe_token)
+VALUES (gen_random_uuid(), 'definitely-not-an-email', 'legacy', now(), 'confirmed', 'legacy')
+"#
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    Mock::given(path("/ru/api/sendEmail"))
+        .and(method("POST"))
+        .respond_with(email_sent_response())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    // Act
+    let response = app.post_newsletters(&newsletter_request_body()).await;
+    app.dispatch_all_pending_emails().await;
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[actix_web::test]
+async fn newsletters_returns_400_for_invalid_data() {
+    // Arrange
+    let app = spawn_app().await;
+    let test_cases = vec![
+        (
+            serde_json::json!({
+                "content": {
+                    "text": "Newsletter body as plain text",
+                    "html": "<p>Newsletter body as HTML</p>",
+                }
+            }),
+            "missing title",
+        ),
+        (
+            serde_json::json!({"title": "Newsletter!"}),
+            "missing content",
+        ),
+    ];
+    for (invalid_body, error_message) in test_cases {
+        // Act
+        let response = app.post_newsletters(&invalid_body).await;
+        // Assert
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not fail with 400 Bad Request when the payload was {}.",
+            error_message
+        );
+    }
+}
+
+#[actix_web::test]
+async fn requests_missing_authorization_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    // Act
+    let response = reqwest::Client::new()
+        .post(format!("{}/newsletters", &app.address))
+        .json(&newsletter_request_body())
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    // Assert
+    assert_eq!(401, response.status().as_u16());
+    assert_eq!(
+        r#"Basic realm="publish""#,
+        response.headers()["WWW-Authenticate"]
+    );
+}
+
+#[actix_web::test]
+async fn non_existing_user_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let username = Uuid::new_v4().to_string();
+    let password = Uuid::new_v4().to_string();
+    // Act
+    let response = reqwest::Client::new()
+        .post(format!("{}/newsletters", &app.address))
+        .basic_auth(username, Some(password))
+        .json(&newsletter_request_body())
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    // Assert
+    assert_eq!(401, response.status().as_u16());
+    assert_eq!(
+        r#"Basic realm="publish""#,
+        response.headers()["WWW-Authenticate"]
+    );
+}
+
+#[actix_web::test]
+async fn invalid_password_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let password = Uuid::new_v4().to_string();
+    assert_ne!(app.test_user.password, password);
+    // Act
+    let response = reqwest::Client::new()
+        .post(format!("{}/newsletters", &app.address))
+        .basic_auth(&app.test_user.username, Some(password))
+        .json(&newsletter_request_body())
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    // Assert
+    assert_eq!(401, response.status().as_u16());
+    assert_eq!(
+        r#"Basic realm="publish""#,
+        response.headers()["WWW-Authenticate"]
+    );
+}
+
+/// Publish the test newsletter and return the id of the new issue
+async fn publish_newsletter(app: &TestApp) -> String {
+    let response = app.post_newsletters(&newsletter_request_body()).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    body["newsletter_issue_id"].as_str().unwrap().to_owned()
+}
+
+#[actix_web::test]
+async fn issue_status_tracks_queued_and_sent_deliveries() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/ru/api/sendEmail"))
+        .and(method("POST"))
+        .respond_with(email_sent_response())
+        .mount(&app.email_server)
+        .await;
+    app.login().await;
+    let newsletter_issue_id = publish_newsletter(&app).await;
+    // Act - Part 1 - Before delivery
+    let response = app.get_newsletter_issue_status(&newsletter_issue_id).await;
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let status: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(status["title"], "Newsletter title");
+    assert_eq!(
+        status["counts"],
+        serde_json::json!({"queued": 1, "sent": 0, "failed": 0, "cancelled": 0})
+    );
+    // Act - Part 2 - After delivery
+    app.dispatch_all_pending_emails().await;
+    let status: serde_json::Value = app
+        .get_newsletter_issue_status(&newsletter_issue_id)
+        .await
+        .json()
+        .await
+        .unwrap();
+    // Assert
+    assert_eq!(
+        status["counts"],
+        serde_json::json!({"queued": 0, "sent": 1, "failed": 0, "cancelled": 0})
+    );
+    assert_eq!(status["failures"], serde_json::json!([]));
+}
+
+#[actix_web::test]
+async fn issue_status_reports_failed_deliveries_with_their_error() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/ru/api/sendEmail"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "result": {
+                "email_id": "some id",
+                "errors": [{"code": "unsubscribed", "message": "Recipient unsubscribed"}]
+            }
+        })))
+        .mount(&app.email_server)
+        .await;
+    app.login().await;
+    let newsletter_issue_id = publish_newsletter(&app).await;
+    // Act
+    app.dispatch_all_pending_emails().await;
+    let status: serde_json::Value = app
+        .get_newsletter_issue_status(&newsletter_issue_id)
+        .await
+        .json()
+        .await
+        .unwrap();
+    // Assert
+    assert_eq!(
+        status["counts"],
+        serde_json::json!({"queued": 0, "sent": 0, "failed": 1, "cancelled": 0})
+    );
+    let failure = &status["failures"][0];
+    assert_eq!(failure["subscriber_email"], "ursula_le_guin@gmail.com");
+    assert!(failure["error"]
+        .as_str()
+        .unwrap()
+        .contains("Recipient unsubscribed"));
+}
+
+#[actix_web::test]
+async fn issue_status_of_an_unknown_issue_is_404() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login().await;
+    // Act
+    let response = app
+        .get_newsletter_issue_status(&Uuid::new_v4().to_string())
+        .await;
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[actix_web::test]
+async fn you_must_be_logged_in_to_see_the_issue_status() {
+    // Arrange
+    let app = spawn_app().await;
+    // Act
+    let response = app
+        .get_newsletter_issue_status(&Uuid::new_v4().to_string())
+        .await;
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
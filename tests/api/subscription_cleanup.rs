@@ -0,0 +1,157 @@
+use crate::helpers::{spawn_app, TestApp};
+use zero2prod::subscription_cleanup::{
+    delete_expired_pending_subscriptions, delete_stale_subscriptions, CleanupOutcome,
+};
+
+async fn subscribe(app: &TestApp, email: &str) {
+    let body = format!("name=le%20guin&email={}", email.replace('@', "%40"));
+    app.post_subscriptions(body)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+async fn expire_tokens_of(app: &TestApp, email: &str) {
+    sqlx::query!(
+        r#"
+UPDATE subscription_tokens SET expires_at = now() - interval '1 minute'
+WHERE subscriber_id = (SELECT id FROM subscriptions WHERE email = $1)
+"#,
+        email
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+async fn saved_emails(app: &TestApp) -> Vec<String> {
+    sqlx::query!("SELECT email FROM subscriptions ORDER BY email")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| r.email)
+        .collect()
+}
+
+#[actix_web::test]
+async fn cleanup_deletes_pending_subscribers_whose_tokens_expired() {
+    // Arrange
+    let app = spawn_app().await;
+    subscribe(&app, "stale@gmail.com").await;
+    subscribe(&app, "fresh@gmail.com").await;
+    expire_tokens_of(&app, "stale@gmail.com").await;
+    // Act
+    let outcome = delete_stale_subscriptions(&app.db_pool).await.unwrap();
+    // Assert
+    assert_eq!(
+        outcome,
+        CleanupOutcome {
+            deleted_tokens: 1,
+            deleted_subscribers: 1
+        }
+    );
+    assert_eq!(saved_emails(&app).await, vec!["fresh@gmail.com"]);
+}
+
+#[actix_web::test]
+async fn cleanup_keeps_pending_subscribers_with_a_valid_token_left() {
+    // Arrange
+    let app = spawn_app().await;
+    subscribe(&app, "ursula_le_guin@gmail.com").await;
+    expire_tokens_of(&app, "ursula_le_guin@gmail.com").await;
+    // Subscribing again issues a fresh token
+    subscribe(&app, "ursula_le_guin@gmail.com").await;
+    // Act
+    let outcome = delete_stale_subscriptions(&app.db_pool).await.unwrap();
+    // Assert
+    assert_eq!(outcome.deleted_tokens, 1);
+    assert_eq!(outcome.deleted_subscribers, 0);
+    assert_eq!(saved_emails(&app).await, vec!["ursula_le_guin@gmail.com"]);
+}
+
+#[actix_web::test]
+async fn cleanup_keeps_confirmed_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+    subscribe(&app, "ursula_le_guin@gmail.com").await;
+    sqlx::query!("UPDATE subscriptions SET status = 'confirmed'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    expire_tokens_of(&app, "ursula_le_guin@gmail.com").await;
+    // Act
+    let outcome = delete_stale_subscriptions(&app.db_pool).await.unwrap();
+    // Assert
+    assert_eq!(outcome.deleted_tokens, 1);
+    assert_eq!(outcome.deleted_subscribers, 0);
+    assert_eq!(saved_emails(&app).await, vec!["ursula_le_guin@gmail.com"]);
+}
+
+async fn backdate_confirmation_request_of(app: &TestApp, email: &str) {
+    sqlx::query!(
+        r#"
+UPDATE subscriptions
+SET confirmation_requested_at = now() - interval '2 days'
+WHERE email = $1
+"#,
+        email
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+#[actix_web::test]
+async fn without_tokens_cleanup_deletes_pending_subscribers_older_than_the_ttl() {
+    // Arrange
+    let app = spawn_app().await;
+    subscribe(&app, "stale@gmail.com").await;
+    subscribe(&app, "fresh@gmail.com").await;
+    subscribe(&app, "confirmed@gmail.com").await;
+    sqlx::query!(
+        "UPDATE subscriptions SET status = 'confirmed' WHERE email = 'confirmed@gmail.com'"
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    backdate_confirmation_request_of(&app, "stale@gmail.com").await;
+    backdate_confirmation_request_of(&app, "confirmed@gmail.com").await;
+    // Act
+    let outcome = delete_expired_pending_subscriptions(&app.db_pool, chrono::Duration::days(1))
+        .await
+        .unwrap();
+    // Assert
+    assert_eq!(outcome.deleted_subscribers, 1);
+    assert_eq!(
+        saved_emails(&app).await,
+        vec!["confirmed@gmail.com", "fresh@gmail.com"]
+    );
+}
+
+#[actix_web::test]
+async fn subscribing_again_restarts_the_confirmation_window() {
+    // Arrange
+    let app = spawn_app().await;
+    subscribe(&app, "ursula_le_guin@gmail.com").await;
+    backdate_confirmation_request_of(&app, "ursula_le_guin@gmail.com").await;
+    let subscribed_at = subscribed_at_of(&app).await;
+    // Act
+    subscribe(&app, "ursula_le_guin@gmail.com").await;
+    let outcome = delete_expired_pending_subscriptions(&app.db_pool, chrono::Duration::days(1))
+        .await
+        .unwrap();
+    // Assert
+    assert_eq!(outcome.deleted_subscribers, 0);
+    assert_eq!(saved_emails(&app).await, vec!["ursula_le_guin@gmail.com"]);
+    // The subscriber keeps their place in the paginated listing
+    assert_eq!(subscribed_at_of(&app).await, subscribed_at);
+}
+
+async fn subscribed_at_of(app: &TestApp) -> chrono::DateTime<chrono::Utc> {
+    sqlx::query_scalar!("SELECT subscribed_at FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+}
@@ -1,7 +1,7 @@
 use wiremock::matchers::{method, path};
-use wiremock::{Mock, ResponseTemplate};
+use wiremock::Mock;
 
-use crate::helpers::spawn_app;
+use crate::helpers::{email_sent_response, spawn_app};
 
 // #[actix_web::test]
 // async fn subscribe_returns_a_200_for_valid_form_data() {
@@ -14,7 +14,7 @@ use crate::helpers::spawn_app;
 //         }
 //     );
 //     Mock::given(path("/ru/api/sendEmail"))
-//         .and(method("GET"))
+//         .and(method("POST"))
 //         .respond_with(ResponseTemplate::new(200).set_body_json(body))
 //         .mount(&test_app.email_server)
 //         .await;
@@ -77,70 +77,383 @@ async fn subscribe_returns_a_400_when_fields_are_present_but_empty() {
     }
 }
 
+#[actix_web::test]
+async fn subscribe_returns_the_failing_fields_when_validation_fails() {
+    // Arrange
+    let app = spawn_app().await;
+    let test_cases = vec![
+        ("name=&email=ursula_le_guin%40gmail.com", vec!["name"]),
+        ("name=Ursula&email=definitely-not-an-email", vec!["email"]),
+        ("name=&email=", vec!["name", "email"]),
+    ];
+    for (body, expected_fields) in test_cases {
+        // Act
+        let response = app.post_subscriptions(body.into()).await.unwrap();
+        // Assert
+        assert_eq!(response.status().as_u16(), 400);
+        let errors: serde_json::Value = response.json().await.unwrap();
+        let errors = errors["errors"].as_array().unwrap();
+        let fields: Vec<_> = errors
+            .iter()
+            .map(|e| e["field"].as_str().unwrap())
+            .collect();
+        assert_eq!(fields, expected_fields, "Unexpected errors for {body}");
+        assert!(errors
+            .iter()
+            .all(|e| !e["message"].as_str().unwrap().is_empty()));
+    }
+}
+
 #[actix_web::test]
 async fn subscribe_sends_a_confirmation_email_for_valid_data() {
     // Arrange
     let app = spawn_app().await;
     let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
     Mock::given(path("/ru/api/sendEmail"))
-        .and(method("GET"))
-        .respond_with(ResponseTemplate::new(200))
+        .and(method("POST"))
+        .respond_with(email_sent_response())
         .expect(1)
         .mount(&app.email_server)
         .await;
     // Act
     app.post_subscriptions(body.into()).await.unwrap();
+    app.dispatch_all_pending_emails().await;
     // Assert
+    app.assert_all_emails_were_sent().await;
     // Mock asserts on drop
-    sqlx::query("DELETE FROM subscriptions WHERE email = $1")
-        .bind("ursula_le_guin@gmail.com")
+}
+
+#[actix_web::test]
+async fn subscribe_persists_the_new_subscriber_as_pending() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=le_guin_pending%40gmail.com";
+    Mock::given(path("/ru/api/sendEmail"))
+        .and(method("POST"))
+        .respond_with(email_sent_response())
+        .mount(&app.email_server)
+        .await;
+    // Act
+    app.post_subscriptions(body.into()).await.unwrap();
+    // Assert
+    let saved = sqlx::query!(
+        "SELECT email, name, status FROM subscriptions WHERE email = $1",
+        "le_guin_pending@gmail.com"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.name, "le guin");
+    assert_eq!(saved.status, "pending_confirmation");
+}
+
+#[actix_web::test]
+async fn subscribe_sends_a_confirmation_email_with_a_link() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=le_guin_link%40gmail.com";
+    Mock::given(path("/ru/api/sendEmail"))
+        .and(method("POST"))
+        .respond_with(email_sent_response())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    // Act
+    app.post_subscriptions(body.into()).await.unwrap();
+    app.dispatch_all_pending_emails().await;
+    // Assert
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    // The two links should be identical
+    assert_eq!(confirmation_links.html, confirmation_links.plain_text);
+    assert_eq!(confirmation_links.html.path(), "/subscriptions/confirm");
+    assert!(confirmation_links
+        .html
+        .query_pairs()
+        .any(|(key, value)| key == "subscription_token" && !value.is_empty()));
+}
+
+#[actix_web::test]
+async fn subscribe_persists_nothing_if_storing_the_token_fails() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    // Sabotage the database so that the second statement of the transaction fails
+    sqlx::query("ALTER TABLE subscription_tokens DROP COLUMN subscription_token_hash;")
         .execute(&app.db_pool)
         .await
         .unwrap();
+    // Act
+    let response = app.post_subscriptions(body.into()).await.unwrap();
+    // Assert
+    assert_eq!(response.status().as_u16(), 500);
+    // Internal details are logged, never returned to the client
+    assert!(response.bytes().await.unwrap().is_empty());
+    let saved = sqlx::query!("SELECT count(*) AS \"count!\" FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to count subscriptions.");
+    assert_eq!(saved.count, 0);
 }
 
-// #[tokio::test]
-// async fn subscribe_sends_a_confirmation_email_with_a_link() {
-//     // Arrange
-//     let app = spawn_app().await;
-//     let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
-//     let resp_body = json!(
-//         {
-//             "result": {
-//                 "email_id": "some id"
-//             }
-//         }
-//     );
-//     Mock::given(path("/ru/api/sendEmail"))
-//         .and(method("GET"))
-//         .respond_with(ResponseTemplate::new(200).set_body_json(resp_body))
-//         .expect(1)
-//         .mount(&app.email_server)
-//         .await;
-//     // Act
-//     app.post_subscriptions(body.into()).await.unwrap();
-//     // Assert
-//     // Get the first intercepted request
-//     let email_request = app.email_server.received_requests().await.unwrap();
-//     assert!(!email_request.is_empty());
-//     // Parse the body as JSON, starting from raw bytes
-//     match serde_json::from_slice::<serde_json::Value>(&email_request.first().unwrap().body) {
-//         Ok(body) => {
-//             let get_link = |s: &str| {
-//                 let links: Vec<_> = linkify::LinkFinder::new()
-//                     .links(s)
-//                     .filter(|l| *l.kind() == linkify::LinkKind::Url)
-//                     .collect();
-//                 assert_eq!(links.len(), 1);
-//                 links[0].as_str().to_owned()
-//             };
-//             let html_link = get_link(body["HtmlBody"].as_str().unwrap());
-//             let text_link = get_link(body["TextBody"].as_str().unwrap());
-//             // The two links should be identical
-//             assert_eq!(html_link, text_link);
-//         }
-//         Err(e) => {
-//             assert_eq!(e.to_string(), String::new());
-//         }
-//     }
-// }
+#[actix_web::test]
+async fn subscribe_persists_nothing_if_queueing_the_email_fails() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    sqlx::query("ALTER TABLE email_delivery_queue DROP COLUMN subject;")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    // Act
+    let response = app.post_subscriptions(body.into()).await.unwrap();
+    // Assert
+    assert_eq!(response.status().as_u16(), 500);
+    let subscribers = sqlx::query!("SELECT count(*) AS \"count!\" FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to count subscriptions.");
+    assert_eq!(subscribers.count, 0);
+    let tokens = sqlx::query!("SELECT count(*) AS \"count!\" FROM subscription_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to count subscription tokens.");
+    assert_eq!(tokens.count, 0);
+}
+
+#[actix_web::test]
+async fn subscribing_twice_while_pending_sends_a_new_confirmation_email() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(path("/ru/api/sendEmail"))
+        .and(method("POST"))
+        .respond_with(email_sent_response())
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+    // Act
+    let first = app.post_subscriptions(body.into()).await.unwrap();
+    let second = app.post_subscriptions(body.into()).await.unwrap();
+    app.dispatch_all_pending_emails().await;
+    // Assert
+    assert_eq!(first.status().as_u16(), 200);
+    assert_eq!(second.status().as_u16(), 200);
+    let subscribers = sqlx::query!("SELECT count(*) AS \"count!\" FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to count subscriptions.");
+    assert_eq!(subscribers.count, 1);
+    app.assert_all_emails_were_sent().await;
+    // Both links are usable
+    let email_requests = app.email_server.received_requests().await.unwrap();
+    let first_link = app.get_confirmation_links(&email_requests[0]).html;
+    let second_link = app.get_confirmation_links(&email_requests[1]).html;
+    assert_ne!(first_link, second_link);
+    for link in [first_link, second_link] {
+        let response = reqwest::get(link).await.unwrap();
+        assert_eq!(response.status().as_u16(), 200);
+    }
+}
+
+#[actix_web::test]
+async fn subscribing_again_once_confirmed_returns_200_without_sending_an_email() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(path("/ru/api/sendEmail"))
+        .and(method("POST"))
+        .respond_with(email_sent_response())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions(body.into()).await.unwrap();
+    app.dispatch_all_pending_emails().await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    // Act
+    let response = app.post_subscriptions(body.into()).await.unwrap();
+    app.dispatch_all_pending_emails().await;
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscriptions.");
+    assert_eq!(saved.len(), 1);
+    assert_eq!(saved[0].status, "confirmed");
+    app.assert_all_emails_were_sent().await;
+    // Mock asserts on drop that no second email went out
+}
+
+#[actix_web::test]
+async fn subscribing_again_updates_the_name() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/ru/api/sendEmail"))
+        .and(method("POST"))
+        .respond_with(email_sent_response())
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await
+        .unwrap();
+    // Act
+    let response = app
+        .post_subscriptions("name=Ursula%20Le%20Guin&email=ursula_le_guin%40gmail.com".into())
+        .await
+        .unwrap();
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT name, status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.name, "Ursula Le Guin");
+    assert_eq!(saved.status, "pending_confirmation");
+}
+
+#[actix_web::test]
+async fn subscribing_again_does_not_rename_a_confirmed_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/ru/api/sendEmail"))
+        .and(method("POST"))
+        .respond_with(email_sent_response())
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    // Act
+    let response = app
+        .post_subscriptions("name=Someone%20Else&email=ursula_le_guin%40gmail.com".into())
+        .await
+        .unwrap();
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT name, status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.name, "le guin");
+    assert_eq!(saved.status, "confirmed");
+}
+
+#[actix_web::test]
+async fn subscribe_accepts_a_json_body() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = serde_json::json!({"name": "le guin", "email": "ursula_le_guin@gmail.com"});
+    // Act
+    let response = app.post_subscriptions_json(&body).await.unwrap();
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let response: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(
+        response,
+        serde_json::json!({ "status": "pending_confirmation" })
+    );
+    let saved = sqlx::query!("SELECT email, status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.email, "ursula_le_guin@gmail.com");
+    assert_eq!(saved.status, "pending_confirmation");
+}
+
+#[actix_web::test]
+async fn subscribe_returns_json_for_form_data_when_the_client_accepts_it() {
+    // Arrange
+    let app = spawn_app().await;
+    // Act
+    let response = reqwest::Client::new()
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("Accept", "application/json")
+        .body("name=le%20guin&email=ursula_le_guin%40gmail.com")
+        .send()
+        .await
+        .unwrap();
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let response: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(response["status"], "pending_confirmation");
+}
+
+#[actix_web::test]
+async fn subscribe_does_not_reveal_to_json_clients_that_the_email_is_confirmed() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = serde_json::json!({"name": "le guin", "email": "ursula_le_guin@gmail.com"});
+    Mock::given(path("/ru/api/sendEmail"))
+        .and(method("POST"))
+        .respond_with(email_sent_response())
+        .mount(&app.email_server)
+        .await;
+    let first_response = app
+        .post_subscriptions_json(&body)
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    // Act
+    let response = app.post_subscriptions_json(&body).await.unwrap();
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.text().await.unwrap(), first_response);
+}
+
+#[actix_web::test]
+async fn subscribe_returns_a_400_for_invalid_json_bodies() {
+    // Arrange
+    let app = spawn_app().await;
+    let test_cases = vec![
+        (serde_json::json!({"name": "le guin"}), "missing the email"),
+        (
+            serde_json::json!({"email": "ursula_le_guin@gmail.com"}),
+            "missing the name",
+        ),
+        (
+            serde_json::json!({"name": "", "email": "ursula_le_guin@gmail.com"}),
+            "empty name",
+        ),
+        (
+            serde_json::json!({"name": "Ursula", "email": "not-an-email"}),
+            "invalid email",
+        ),
+    ];
+    for (body, description) in test_cases {
+        // Act
+        let response = app.post_subscriptions_json(&body).await.unwrap();
+        // Assert
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not return a 400 Bad Request when the payload was {}.",
+            description
+        );
+    }
+}
@@ -0,0 +1,288 @@
+use wiremock::matchers::{method, path};
+use wiremock::Mock;
+
+use crate::helpers::{email_sent_response, spawn_app, spawn_app_with, TestApp};
+use zero2prod::configuration::{ConfirmationTokenMode, Settings};
+
+#[actix_web::test]
+async fn confirmations_without_token_are_rejected_with_a_400() {
+    // Arrange
+    let app = spawn_app().await;
+    // Act
+    let response = reqwest::get(&format!("{}/subscriptions/confirm", app.address))
+        .await
+        .unwrap();
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[actix_web::test]
+async fn confirmations_with_an_unknown_token_are_rejected_with_a_401() {
+    // Arrange
+    let app = spawn_app().await;
+    // Act
+    let response = reqwest::get(&format!(
+        "{}/subscriptions/confirm?subscription_token=unknowntoken",
+        app.address
+    ))
+    .await
+    .unwrap();
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[actix_web::test]
+async fn the_link_returned_by_subscribe_returns_a_200_if_called() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=le_guin_click%40gmail.com";
+    Mock::given(path("/ru/api/sendEmail"))
+        .and(method("POST"))
+        .respond_with(email_sent_response())
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions(body.into()).await.unwrap();
+    app.dispatch_all_pending_emails().await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    // Act
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    app.assert_all_emails_were_sent().await;
+}
+
+#[actix_web::test]
+async fn clicking_on_the_confirmation_link_confirms_a_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=le_guin_confirm%40gmail.com";
+    Mock::given(path("/ru/api/sendEmail"))
+        .and(method("POST"))
+        .respond_with(email_sent_response())
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions(body.into()).await.unwrap();
+    app.dispatch_all_pending_emails().await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    // Act
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    // Assert
+    let saved = sqlx::query!(
+        "SELECT email, name, status FROM subscriptions WHERE email = $1",
+        "le_guin_confirm@gmail.com"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.name, "le guin");
+    assert_eq!(saved.status, "confirmed");
+}
+
+#[actix_web::test]
+async fn expired_confirmation_links_are_rejected_with_a_410() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(path("/ru/api/sendEmail"))
+        .and(method("POST"))
+        .respond_with(email_sent_response())
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions(body.into()).await.unwrap();
+    app.dispatch_all_pending_emails().await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    sqlx::query!("UPDATE subscription_tokens SET expires_at = now() - interval '1 minute'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    // Act
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+    // Assert
+    assert_eq!(response.status().as_u16(), 410);
+    assert!(response.text().await.unwrap().contains("expired"));
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.status, "pending_confirmation");
+}
+
+#[actix_web::test]
+async fn confirmation_tokens_expire_after_the_configured_ttl() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    // Act
+    app.post_subscriptions(body.into()).await.unwrap();
+    // Assert
+    let saved =
+        sqlx::query!(r#"SELECT expires_at - created_at AS "ttl!" FROM subscription_tokens"#)
+            .fetch_one(&app.db_pool)
+            .await
+            .expect("Failed to fetch saved token.");
+    let ttl_seconds = saved.ttl.microseconds / 1_000_000 + i64::from(saved.ttl.days) * 86_400;
+    // 1 day in configuration/base.yaml, allowing for the time between the two timestamps
+    assert!((86_399..=86_400).contains(&ttl_seconds));
+}
+
+#[actix_web::test]
+async fn only_a_digest_of_the_confirmation_token_is_stored() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(path("/ru/api/sendEmail"))
+        .and(method("POST"))
+        .respond_with(email_sent_response())
+        .mount(&app.email_server)
+        .await;
+    // Act
+    app.post_subscriptions(body.into()).await.unwrap();
+    app.dispatch_all_pending_emails().await;
+    // Assert
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    let token = confirmation_links
+        .html
+        .query_pairs()
+        .find_map(|(key, value)| (key == "subscription_token").then(|| value.into_owned()))
+        .unwrap();
+    let saved = sqlx::query!("SELECT subscription_token_hash FROM subscription_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved token.");
+    assert_ne!(saved.subscription_token_hash, token);
+    assert_eq!(saved.subscription_token_hash.len(), 64);
+}
+
+fn use_signed_tokens(configuration: &mut Settings) {
+    configuration.subscriptions.confirmation_tokens.mode = ConfirmationTokenMode::Signed;
+}
+
+/// Subscribe and return the link of the confirmation email
+async fn subscribe_and_get_confirmation_link(app: &TestApp) -> reqwest::Url {
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(path("/ru/api/sendEmail"))
+        .and(method("POST"))
+        .respond_with(email_sent_response())
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions(body.into())
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    app.get_confirmation_links(email_request).html
+}
+
+async fn saved_status(app: &TestApp) -> String {
+    sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.")
+        .status
+}
+
+#[actix_web::test]
+async fn signed_confirmation_links_confirm_without_storing_a_token() {
+    // Arrange
+    let app = spawn_app_with(use_signed_tokens).await;
+    let confirmation_link = subscribe_and_get_confirmation_link(&app).await;
+    // Act
+    let response = reqwest::get(confirmation_link).await.unwrap();
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(saved_status(&app).await, "confirmed");
+    let tokens = sqlx::query!("SELECT count(*) AS \"count!\" FROM subscription_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(tokens.count, 0);
+}
+
+#[actix_web::test]
+async fn tampered_signed_confirmation_links_are_rejected_with_a_401() {
+    // Arrange
+    let app = spawn_app_with(use_signed_tokens).await;
+    let mut confirmation_link = subscribe_and_get_confirmation_link(&app).await;
+    let token = confirmation_link
+        .query_pairs()
+        .find_map(|(key, value)| (key == "subscription_token").then(|| value.into_owned()))
+        .unwrap();
+    let (_, claims_and_signature) = token.split_once('.').unwrap();
+    let tampered = format!("{}.{claims_and_signature}", uuid::Uuid::new_v4());
+    confirmation_link
+        .query_pairs_mut()
+        .clear()
+        .append_pair("subscription_token", &tampered);
+    // Act
+    let response = reqwest::get(confirmation_link).await.unwrap();
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(saved_status(&app).await, "pending_confirmation");
+}
+
+#[actix_web::test]
+async fn expired_signed_confirmation_links_are_rejected_with_a_410() {
+    // Arrange
+    let app = spawn_app_with(|configuration| {
+        use_signed_tokens(configuration);
+        configuration.subscriptions.token_ttl = 0;
+    })
+    .await;
+    let confirmation_link = subscribe_and_get_confirmation_link(&app).await;
+    // Act
+    let response = reqwest::get(confirmation_link).await.unwrap();
+    // Assert
+    assert_eq!(response.status().as_u16(), 410);
+    assert_eq!(saved_status(&app).await, "pending_confirmation");
+}
+
+#[actix_web::test]
+async fn signed_confirmation_links_of_deleted_subscribers_are_rejected_with_a_401() {
+    // Arrange
+    let app = spawn_app_with(use_signed_tokens).await;
+    let confirmation_link = subscribe_and_get_confirmation_link(&app).await;
+    sqlx::query!("DELETE FROM subscriptions")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    // Act
+    let response = reqwest::get(confirmation_link).await.unwrap();
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[actix_web::test]
+async fn signed_confirmation_links_of_unsubscribed_subscribers_are_rejected_with_a_401() {
+    // Arrange
+    let app = spawn_app_with(use_signed_tokens).await;
+    let confirmation_link = subscribe_and_get_confirmation_link(&app).await;
+    sqlx::query!("UPDATE subscriptions SET status = 'unsubscribed'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    // Act
+    let response = reqwest::get(confirmation_link).await.unwrap();
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(saved_status(&app).await, "unsubscribed");
+}
+
+#[actix_web::test]
+#[should_panic(expected = "No `subscriptions.confirmation_tokens.signing_key` configured")]
+async fn the_application_does_not_start_in_signed_mode_without_a_signing_key() {
+    spawn_app_with(|configuration| {
+        use_signed_tokens(configuration);
+        configuration.subscriptions.confirmation_tokens.signing_key = None;
+    })
+    .await;
+}
@@ -0,0 +1,219 @@
+use crate::helpers::{email_sent_response, spawn_app, TestApp};
+use wiremock::matchers::{method, path};
+use wiremock::Mock;
+
+/// Subscribe and confirm, returning the unsubscribe link found in the confirmation email
+async fn create_confirmed_subscriber(app: &TestApp) -> reqwest::Url {
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    let _mock_guard = Mock::given(path("/ru/api/sendEmail"))
+        .and(method("POST"))
+        .respond_with(email_sent_response())
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.post_subscriptions(body.into()).await.unwrap();
+    app.dispatch_all_pending_emails().await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    app.get_unsubscribe_link(email_request)
+}
+
+async fn saved_status(app: &TestApp) -> String {
+    sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.")
+        .status
+}
+
+#[actix_web::test]
+async fn emails_carry_list_unsubscribe_headers_matching_the_footer_link() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/ru/api/sendEmail"))
+        .and(method("POST"))
+        .respond_with(email_sent_response())
+        .mount(&app.email_server)
+        .await;
+    // Act
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+    // Assert
+    app.assert_all_emails_were_sent().await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let headers = form_urlencoded::parse(&email_request.body)
+        .find(|(key, _)| key == "headers")
+        .map(|(_, value)| value.into_owned())
+        .expect("No headers in the email request");
+    let unsubscribe_link = app.get_unsubscribe_link(email_request);
+    let list_unsubscribe = headers
+        .lines()
+        .find_map(|line| line.strip_prefix("List-Unsubscribe: "))
+        .expect("No List-Unsubscribe header");
+    assert!(list_unsubscribe.starts_with('<') && list_unsubscribe.ends_with('>'));
+    assert!(list_unsubscribe.ends_with(&format!("?{}>", unsubscribe_link.query().unwrap())));
+    assert!(headers.contains("List-Unsubscribe-Post: List-Unsubscribe=One-Click"));
+}
+
+#[actix_web::test]
+async fn the_unsubscribe_link_shows_a_confirmation_page_without_unsubscribing() {
+    // Arrange
+    let app = spawn_app().await;
+    let unsubscribe_link = create_confirmed_subscriber(&app).await;
+    // Act
+    let response = reqwest::get(unsubscribe_link).await.unwrap();
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.text().await.unwrap().contains("<form"));
+    assert_eq!(saved_status(&app).await, "confirmed");
+}
+
+#[actix_web::test]
+async fn posting_to_the_unsubscribe_link_unsubscribes_the_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    let unsubscribe_link = create_confirmed_subscriber(&app).await;
+    // Act - One-click unsubscribe as sent by mail clients
+    let response = reqwest::Client::new()
+        .post(unsubscribe_link)
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body("List-Unsubscribe=One-Click")
+        .send()
+        .await
+        .unwrap();
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(saved_status(&app).await, "unsubscribed");
+}
+
+#[actix_web::test]
+async fn unsubscribed_subscribers_do_not_receive_newsletters() {
+    // Arrange
+    let app = spawn_app().await;
+    let unsubscribe_link = create_confirmed_subscriber(&app).await;
+    reqwest::Client::new()
+        .post(unsubscribe_link)
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    Mock::given(path("/ru/api/sendEmail"))
+        .and(method("POST"))
+        .respond_with(email_sent_response())
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    // Act
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "title": "Newsletter title",
+            "content": {"text": "Plain text", "html": "<p>HTML</p>"}
+        }))
+        .await;
+    app.dispatch_all_pending_emails().await;
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    // Mock verifies on Drop that we haven't sent the newsletter email
+}
+
+#[actix_web::test]
+async fn unsubscribing_cancels_the_emails_already_queued() {
+    // Arrange
+    let app = spawn_app().await;
+    let unsubscribe_link = create_confirmed_subscriber(&app).await;
+    app.post_newsletters(&serde_json::json!({
+        "title": "Newsletter title",
+        "content": {"text": "Plain text", "html": "<p>HTML</p>"}
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+    Mock::given(path("/ru/api/sendEmail"))
+        .and(method("POST"))
+        .respond_with(email_sent_response())
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    // Act
+    reqwest::Client::new()
+        .post(unsubscribe_link)
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+    // Assert
+    let queued =
+        sqlx::query!("SELECT status FROM email_delivery_queue WHERE subject = 'Newsletter title'")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+    assert_eq!(queued.status, "cancelled");
+    // Mock verifies on Drop that we haven't sent the newsletter email
+}
+
+#[actix_web::test]
+async fn subscribing_again_after_unsubscribing_asks_for_a_new_confirmation() {
+    // Arrange
+    let app = spawn_app().await;
+    let unsubscribe_link = create_confirmed_subscriber(&app).await;
+    reqwest::Client::new()
+        .post(unsubscribe_link)
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    Mock::given(path("/ru/api/sendEmail"))
+        .and(method("POST"))
+        .respond_with(email_sent_response())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    // Act
+    let response = app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(saved_status(&app).await, "pending_confirmation");
+    app.assert_all_emails_were_sent().await;
+}
+
+#[actix_web::test]
+async fn unknown_unsubscribe_tokens_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let url = format!(
+        "{}/subscriptions/unsubscribe?unsubscribe_token=unknown",
+        app.address
+    );
+    // Act
+    let get_response = reqwest::get(&url).await.unwrap();
+    let post_response = reqwest::Client::new().post(&url).send().await.unwrap();
+    // Assert
+    assert_eq!(get_response.status().as_u16(), 401);
+    assert_eq!(post_response.status().as_u16(), 401);
+}
+
+#[actix_web::test]
+async fn unsubscribe_requests_without_a_token_are_rejected_with_400() {
+    // Arrange
+    let app = spawn_app().await;
+    // Act
+    let response = reqwest::get(format!("{}/subscriptions/unsubscribe", app.address))
+        .await
+        .unwrap();
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}